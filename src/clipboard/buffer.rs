@@ -0,0 +1,340 @@
+use super::history::{ClipboardHistory, Snapshot, DEFAULT_HISTORY_LIMIT};
+use super::refresh::{self, EntryStatus};
+use super::trim::{self, FitReport, SizeTarget, TrimStrategy};
+use crate::error::Error;
+use crate::models::{CopiedFile, FileSelection};
+use crate::output::OutputFormatter;
+use crate::paths::Workspace;
+use serde::{Deserialize, Serialize};
+
+// Where an entry ended up after a move; `position` is 1-based
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MovedEntry {
+    pub path: String,
+    pub position: usize,
+}
+
+// What `sort_clipboard` orders entries by
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortKey {
+    Path,
+    Time,
+}
+
+impl SortKey {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "path" => Ok(SortKey::Path),
+            "time" => Ok(SortKey::Time),
+            other => Err(format!("Unknown sort key '{}' (expected 'path' or 'time')", other)),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            SortKey::Path => "path",
+            SortKey::Time => "time",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClipboardBuffer {
+    pub copied_files: Vec<CopiedFile>,
+    #[serde(skip)]
+    history: ClipboardHistory,
+}
+
+impl ClipboardBuffer {
+    pub const fn new() -> Self {
+        Self {
+            copied_files: Vec::new(),
+            history: ClipboardHistory::new(DEFAULT_HISTORY_LIMIT),
+        }
+    }
+
+    // Builds the entry for a copy, merging a selection into an existing
+    // partial entry of the same file
+    pub(super) fn prepare_copy(
+        &self,
+        file_path: String,
+        content: String,
+        selection: Option<FileSelection>,
+    ) -> CopiedFile {
+        match selection {
+            Some(sel) => {
+                match self
+                    .copied_files
+                    .iter()
+                    .find(|f| f.base_path == file_path && f.is_partial())
+                {
+                    Some(existing) => {
+                        let mut merged = existing.clone();
+                        merged.add_selection(sel);
+                        merged
+                    }
+                    None => CopiedFile::from_selection(file_path, sel),
+                }
+            }
+            None => CopiedFile::new(file_path.clone(), file_path, content),
+        }
+    }
+
+    // Snapshots the buffer before an operation so it can be undone
+    pub(super) fn record(&mut self, label: String) {
+        self.history.record(self.copied_files.clone(), label);
+    }
+
+    // Returns the label of the change that was undone
+    pub fn undo(&mut self) -> Result<String, Error> {
+        let snapshot = self
+            .history
+            .undo(self.copied_files.clone())
+            .ok_or(Error::NothingToUndo)?;
+        self.copied_files = snapshot.files;
+        Ok(snapshot.label)
+    }
+
+    // Returns the label of the change that was redone
+    pub fn redo(&mut self) -> Result<String, Error> {
+        let snapshot = self
+            .history
+            .redo(self.copied_files.clone())
+            .ok_or(Error::NothingToRedo)?;
+        self.copied_files = snapshot.files;
+        Ok(snapshot.label)
+    }
+
+    pub fn history(&self) -> impl Iterator<Item = &Snapshot> {
+        self.history.snapshots()
+    }
+
+    // Restoring is itself recorded, so it can be undone like any other change
+    pub fn restore_snapshot(&mut self, index: usize) -> Result<(), Error> {
+        let files = self
+            .history
+            .snapshot(index)
+            .ok_or(Error::HistoryEntryNotFound { index })?
+            .files
+            .clone();
+
+        self.record(format!("restore history entry {}", index));
+        self.copied_files = files;
+        Ok(())
+    }
+
+    // Rewrites every stored path, including those in the history; entries that
+    // end up with the same path are merged like a recopy, keeping the later one
+    pub fn map_paths(&mut self, map: &impl Fn(&str) -> String) {
+        self.copied_files = map_entry_paths(std::mem::take(&mut self.copied_files), map);
+        self.history.map_files(|files| map_entry_paths(files, map));
+    }
+
+    // Replaces any existing entry with the same base path. When `preserve_position`
+    // is set the entry is updated in place instead of moving to the end.
+    pub(super) fn upsert(&mut self, copied_file: CopiedFile, preserve_position: bool) {
+        let existing = self
+            .copied_files
+            .iter()
+            .position(|f| f.base_path == copied_file.base_path);
+
+        match existing {
+            Some(index) if preserve_position => self.copied_files[index] = copied_file,
+            Some(index) => {
+                self.copied_files.remove(index);
+                self.copied_files.push(copied_file);
+            }
+            None => self.copied_files.push(copied_file),
+        }
+    }
+
+    // Accepts a 1-based position or the base path of an entry
+    pub fn find_entry(&self, key: &str) -> Result<usize, Error> {
+        if let Ok(position) = key.parse::<usize>() {
+            self.check_position(position)?;
+            return Ok(position - 1);
+        }
+
+        self.copied_files
+            .iter()
+            .position(|f| f.base_path == key)
+            .ok_or_else(|| Error::EntryNotInClipboard { path: key.to_string() })
+    }
+
+    fn check_position(&self, position: usize) -> Result<(), Error> {
+        if position == 0 || position > self.copied_files.len() {
+            return Err(Error::PositionOutOfRange {
+                position,
+                len: self.copied_files.len(),
+            });
+        }
+        Ok(())
+    }
+
+    pub fn move_entry(&mut self, key: &str, to_position: usize) -> Result<MovedEntry, Error> {
+        let from = self.find_entry(key)?;
+        self.check_position(to_position)?;
+        let path = self.copied_files[from].base_path.clone();
+        // Already there: nothing changes, so there is nothing to undo either
+        if from + 1 == to_position {
+            return Ok(MovedEntry {
+                path,
+                position: to_position,
+            });
+        }
+
+        self.record(format!("move {}", path));
+        let copied_file = self.copied_files.remove(from);
+        self.copied_files.insert(to_position - 1, copied_file);
+        Ok(MovedEntry {
+            path,
+            position: to_position,
+        })
+    }
+
+    pub fn move_entry_up(&mut self, key: &str) -> Result<MovedEntry, Error> {
+        let index = self.find_entry(key)?;
+        let path = self.copied_files[index].base_path.clone();
+        if index == 0 {
+            return Err(Error::EntryAlreadyFirst { path });
+        }
+        self.record(format!("move {}", path));
+        self.copied_files.swap(index, index - 1);
+        Ok(MovedEntry { path, position: index })
+    }
+
+    pub fn move_entry_down(&mut self, key: &str) -> Result<MovedEntry, Error> {
+        let index = self.find_entry(key)?;
+        let path = self.copied_files[index].base_path.clone();
+        if index + 1 == self.copied_files.len() {
+            return Err(Error::EntryAlreadyLast { path });
+        }
+        self.record(format!("move {}", path));
+        self.copied_files.swap(index, index + 1);
+        Ok(MovedEntry {
+            path,
+            position: index + 2,
+        })
+    }
+
+    pub fn sort_by_path(&mut self) {
+        self.record("sort by path".to_string());
+        self.copied_files.sort_by(|a, b| a.base_path.cmp(&b.base_path));
+    }
+
+    // Stable, so entries copied at the same instant keep their relative order
+    pub fn sort_by_copy_time(&mut self) {
+        self.record("sort by copy time".to_string());
+        self.copied_files.sort_by_key(|f| f.copied_at);
+    }
+
+    pub fn reverse(&mut self) {
+        self.record("reverse".to_string());
+        self.copied_files.reverse();
+    }
+
+    // Trimmed entries replace the originals; undo brings the full text back
+    pub fn fit_to_budget(
+        &mut self,
+        formatter: &dyn OutputFormatter,
+        target: SizeTarget,
+        strategies: &[TrimStrategy],
+    ) -> FitReport {
+        let (files, report) = trim::fit_to_budget(&self.copied_files, formatter, target, strategies);
+        if !report.cuts.is_empty() {
+            self.record(format!("fit to {} {}", target.limit, target.unit_name()));
+            self.copied_files = files;
+        }
+        report
+    }
+
+    // Reads every entry's file from disk and reports (display path, status)
+    pub fn check_staleness(&self, workspace: &Workspace) -> Vec<(String, EntryStatus)> {
+        self.copied_files
+            .iter()
+            .map(|f| {
+                let source = refresh::read_source(&workspace.absolute(&f.base_path));
+                (f.display_path.clone(), refresh::check_entry(f, source))
+            })
+            .collect()
+    }
+
+    // Replaces changed entries in place; missing or unusable files keep their old copy
+    pub fn refresh(&mut self, workspace: &Workspace) -> Vec<(String, EntryStatus)> {
+        let mut results = Vec::with_capacity(self.copied_files.len());
+        let mut refreshed = self.copied_files.clone();
+
+        for copied_file in &mut refreshed {
+            let source = refresh::read_source(&workspace.absolute(&copied_file.base_path));
+            let (recaptured, status) = refresh::recapture_entry(copied_file, source);
+            results.push((copied_file.display_path.clone(), status));
+            if let Some(recaptured) = recaptured {
+                *copied_file = recaptured;
+            }
+        }
+
+        if results.iter().any(|(_, status)| *status == EntryStatus::Changed) {
+            self.record("refresh".to_string());
+            self.copied_files = refreshed;
+        }
+        results
+    }
+
+    pub fn list_entries(&self) -> Vec<String> {
+        self.copied_files
+            .iter()
+            .enumerate()
+            .map(|(index, f)| format!("{}. {}", index + 1, f.display_path))
+            .collect()
+    }
+
+    pub fn clear(&mut self) {
+        if !self.copied_files.is_empty() {
+            self.record("clear".to_string());
+        }
+        self.copied_files.clear();
+    }
+
+    pub fn render(&self, formatter: &dyn OutputFormatter) -> String {
+        formatter.render(&self.copied_files)
+    }
+
+    pub fn len(&self) -> usize {
+        self.copied_files.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.copied_files.is_empty()
+    }
+
+    pub fn remove_file(&mut self, base_path: &str) -> bool {
+        if self.contains_file(base_path) {
+            self.record(format!("remove {}", base_path));
+        }
+        let initial_len = self.copied_files.len();
+        self.copied_files.retain(|f| f.base_path != base_path);
+        self.copied_files.len() != initial_len
+    }
+
+    pub fn contains_file(&self, base_path: &str) -> bool {
+        self.copied_files.iter().any(|f| f.base_path == base_path)
+    }
+}
+
+fn map_entry_paths(files: Vec<CopiedFile>, map: &impl Fn(&str) -> String) -> Vec<CopiedFile> {
+    let mut mapped: Vec<CopiedFile> = Vec::new();
+    for mut file in files {
+        file.rebase(map(&file.base_path));
+        match mapped.iter().position(|f| f.base_path == file.base_path) {
+            Some(index) => mapped[index] = file,
+            None => mapped.push(file),
+        }
+    }
+    mapped
+}
+
+impl Default for ClipboardBuffer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
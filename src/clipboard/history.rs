@@ -0,0 +1,151 @@
+use crate::models::CopiedFile;
+use chrono::{DateTime, Utc};
+
+pub const DEFAULT_HISTORY_LIMIT: usize = 50;
+
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    pub files: Vec<CopiedFile>,
+    pub taken_at: DateTime<Utc>,
+    // The operation that replaced this state
+    pub label: String,
+}
+
+// Kept in memory only: every snapshot is a full copy of the buffer, so saving
+// them would multiply the size of the state file
+#[derive(Debug, Clone)]
+pub struct ClipboardHistory {
+    undo: Vec<Snapshot>,
+    redo: Vec<Snapshot>,
+    limit: usize,
+}
+
+impl ClipboardHistory {
+    pub const fn new(limit: usize) -> Self {
+        Self {
+            undo: Vec::new(),
+            redo: Vec::new(),
+            limit,
+        }
+    }
+
+    pub fn record(&mut self, files: Vec<CopiedFile>, label: String) {
+        self.undo.push(Snapshot {
+            files,
+            taken_at: Utc::now(),
+            label,
+        });
+        if self.undo.len() > self.limit {
+            self.undo.remove(0);
+        }
+        self.redo.clear();
+    }
+
+    pub fn undo(&mut self, current: Vec<CopiedFile>) -> Option<Snapshot> {
+        let snapshot = self.undo.pop()?;
+        self.redo.push(Snapshot {
+            files: current,
+            taken_at: Utc::now(),
+            label: snapshot.label.clone(),
+        });
+        Some(snapshot)
+    }
+
+    pub fn redo(&mut self, current: Vec<CopiedFile>) -> Option<Snapshot> {
+        let snapshot = self.redo.pop()?;
+        self.undo.push(Snapshot {
+            files: current,
+            taken_at: Utc::now(),
+            label: snapshot.label.clone(),
+        });
+        Some(snapshot)
+    }
+
+    // Most recent first, matching the 1-based indices accepted by `snapshot`
+    pub fn snapshots(&self) -> impl Iterator<Item = &Snapshot> {
+        self.undo.iter().rev()
+    }
+
+    pub fn snapshot(&self, index: usize) -> Option<&Snapshot> {
+        self.snapshots().nth(index.checked_sub(1)?)
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+
+    // Rewrites the files of every snapshot
+    pub fn map_files(&mut self, mut map: impl FnMut(Vec<CopiedFile>) -> Vec<CopiedFile>) {
+        for snapshot in self.undo.iter_mut().chain(self.redo.iter_mut()) {
+            snapshot.files = map(std::mem::take(&mut snapshot.files));
+        }
+    }
+}
+
+impl Default for ClipboardHistory {
+    fn default() -> Self {
+        Self::new(DEFAULT_HISTORY_LIMIT)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn files(paths: &[&str]) -> Vec<CopiedFile> {
+        paths
+            .iter()
+            .map(|p| CopiedFile::new(p.to_string(), p.to_string(), String::new()))
+            .collect()
+    }
+
+    fn paths(files: &[CopiedFile]) -> Vec<&str> {
+        files.iter().map(|f| f.base_path.as_str()).collect()
+    }
+
+    #[test]
+    fn test_undo_redo_round_trip() {
+        let mut history = ClipboardHistory::default();
+        history.record(files(&["a.rs"]), "copy b.rs".to_string());
+
+        let restored = history.undo(files(&["a.rs", "b.rs"])).unwrap();
+        assert_eq!(paths(&restored.files), vec!["a.rs"]);
+        assert!(!history.can_undo());
+
+        let restored = history.redo(files(&["a.rs"])).unwrap();
+        assert_eq!(paths(&restored.files), vec!["a.rs", "b.rs"]);
+        assert!(history.can_undo());
+        assert!(!history.can_redo());
+    }
+
+    #[test]
+    fn test_record_clears_redo() {
+        let mut history = ClipboardHistory::default();
+        history.record(files(&[]), "copy a.rs".to_string());
+        history.undo(files(&["a.rs"]));
+        assert!(history.can_redo());
+
+        history.record(files(&[]), "copy b.rs".to_string());
+        assert!(!history.can_redo());
+    }
+
+    #[test]
+    fn test_history_is_bounded() {
+        let mut history = ClipboardHistory::new(2);
+        history.record(files(&["1.rs"]), "one".to_string());
+        history.record(files(&["2.rs"]), "two".to_string());
+        history.record(files(&["3.rs"]), "three".to_string());
+
+        let labels: Vec<_> = history.snapshots().map(|s| s.label.as_str()).collect();
+        assert_eq!(labels, vec!["three", "two"]);
+        assert_eq!(paths(&history.snapshot(2).unwrap().files), vec!["2.rs"]);
+        assert!(history.snapshot(0).is_none());
+        assert!(history.snapshot(3).is_none());
+    }
+
+}
@@ -1,14 +1,67 @@
+mod buffer;
+mod history;
+mod refresh;
+mod trim;
+
+use crate::error::Error;
 use crate::models::{CopiedFile, FileSelection};
+use crate::output::{OutputFormatter, PlainFormatter};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fs;
 
+pub use buffer::{ClipboardBuffer, MovedEntry, SortKey};
+pub use refresh::EntryStatus;
+pub use trim::{LimitUnit, SizeTarget, TrimStrategy};
+
+pub const DEFAULT_BUFFER: &str = "default";
+
+static EMPTY_BUFFER: ClipboardBuffer = ClipboardBuffer::new();
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ClipboardManager {
-    pub copied_files: Vec<CopiedFile>,
+    pub buffers: BTreeMap<String, ClipboardBuffer>,
+    #[serde(default = "default_buffer_name")]
+    pub active_buffer: String,
+    // When set, re-copying a file updates its entry in place instead of moving it to the end
+    #[serde(default)]
+    pub preserve_position_on_recopy: bool,
+}
+
+fn default_buffer_name() -> String {
+    DEFAULT_BUFFER.to_string()
 }
 
 impl ClipboardManager {
     pub fn new() -> Self {
+        let mut buffers = BTreeMap::new();
+        buffers.insert(DEFAULT_BUFFER.to_string(), ClipboardBuffer::new());
+
         Self {
-            copied_files: Vec::new(),
+            buffers,
+            active_buffer: DEFAULT_BUFFER.to_string(),
+            preserve_position_on_recopy: false,
+        }
+    }
+
+    pub fn active(&self) -> &ClipboardBuffer {
+        self.buffers.get(&self.active_buffer).unwrap_or(&EMPTY_BUFFER)
+    }
+
+    pub fn active_mut(&mut self) -> &mut ClipboardBuffer {
+        self.buffers.entry(self.active_buffer.clone()).or_default()
+    }
+
+    pub fn buffer(&self, name: &str) -> Result<&ClipboardBuffer, Error> {
+        self.buffers
+            .get(name)
+            .ok_or_else(|| Error::BufferNotFound { buffer: name.to_string() })
+    }
+
+    // Rewrites the stored paths of every buffer
+    pub fn map_paths(&mut self, map: impl Fn(&str) -> String) {
+        for buffer in self.buffers.values_mut() {
+            buffer.map_paths(&map);
         }
     }
 
@@ -17,105 +70,176 @@ impl ClipboardManager {
         file_path: String,
         content: String,
         selection: Option<FileSelection>,
-    ) -> Result<String, String> {
-        let display_path = if let Some(sel) = &selection {
-            sel.format_path_with_lines(&file_path)
-        } else {
-            file_path.clone()
-        };
+    ) -> Result<String, Error> {
+        let name = self.active_buffer.clone();
+        self.copy_file_to_buffer(&name, file_path, content, selection)
+    }
 
-        let file_content = if let Some(sel) = selection {
-            sel.content
-        } else {
-            content
-        };
+    // Copies into a named buffer without switching to it, creating it if needed
+    pub fn copy_file_to_buffer(
+        &mut self,
+        name: &str,
+        file_path: String,
+        content: String,
+        selection: Option<FileSelection>,
+    ) -> Result<String, Error> {
+        validate_buffer_name(name)?;
+        let preserve_position = self.preserve_position_on_recopy;
+        let buffer = self.buffers.entry(name.to_string()).or_default();
+
+        let copied_file = buffer.prepare_copy(file_path, content, selection);
+        buffer.record(format!("copy {}", copied_file.display_path));
+        buffer.upsert(copied_file, preserve_position);
 
-        let copied_file = CopiedFile::new(display_path, file_path, file_content);
+        Ok(buffer.render(&PlainFormatter))
+    }
+
+    pub fn copy_multiple_files(&mut self, file_paths: Vec<String>) -> Result<String, Error> {
+        // Files that can't be read are skipped
+        let files: Vec<(String, String)> = file_paths
+            .into_iter()
+            .filter_map(|file_path| fs::read_to_string(&file_path).ok().map(|content| (file_path, content)))
+            .collect();
 
-        // Remove existing file with same base path
-        self.copied_files.retain(|f| f.base_path != copied_file.base_path);
-        self.copied_files.push(copied_file);
+        if files.is_empty() {
+            return Err(Error::NoReadableFiles { folder: None });
+        }
 
+        let name = self.active_buffer.clone();
+        let label = format!("copy {} files", files.len());
+        self.copy_files_to_buffer(&name, files, label)?;
         Ok(self.generate_combined_content())
     }
 
-    pub fn copy_multiple_files(&mut self, file_paths: Vec<String>) -> Result<String, String> {
-        let mut successful_copies = 0;
-
-        for file_path in file_paths {
-            match fs::read_to_string(&file_path) {
-                Ok(content) => {
-                    let copied_file = CopiedFile::new(
-                        file_path.clone(),
-                        file_path.clone(),
-                        content,
-                    );
-
-                    // Remove existing file with same base path
-                    self.copied_files.retain(|f| f.base_path != copied_file.base_path);
-                    self.copied_files.push(copied_file);
-                    successful_copies += 1;
-                }
-                Err(_) => {
-                    // Skip files that can't be read
-                    continue;
-                }
-            }
+    // Adds already-read (path, content) pairs to a buffer as one undoable step
+    pub fn copy_files_to_buffer(
+        &mut self,
+        name: &str,
+        files: Vec<(String, String)>,
+        label: String,
+    ) -> Result<usize, Error> {
+        validate_buffer_name(name)?;
+        let preserve_position = self.preserve_position_on_recopy;
+        let buffer = self.buffers.entry(name.to_string()).or_default();
+
+        buffer.record(label);
+        let count = files.len();
+        for (file_path, content) in files {
+            buffer.upsert(CopiedFile::new(file_path.clone(), file_path, content), preserve_position);
         }
+        Ok(count)
+    }
+
+    // Reports whether the buffer had to be created
+    pub fn switch_buffer(&mut self, name: &str) -> Result<bool, Error> {
+        validate_buffer_name(name)?;
+        let created = !self.buffers.contains_key(name);
+        self.buffers.entry(name.to_string()).or_default();
+        self.active_buffer = name.to_string();
+        Ok(created)
+    }
 
-        if successful_copies == 0 {
-            return Err("No files could be read successfully".to_string());
+    // (name, file count, is active)
+    pub fn list_buffers(&self) -> Vec<(&str, usize, bool)> {
+        self.buffers
+            .iter()
+            .map(|(name, buffer)| (name.as_str(), buffer.len(), *name == self.active_buffer))
+            .collect()
+    }
+
+    // Appends every entry of `source` to `target`; `source` is left untouched.
+    // Returns the number of files in `target` afterwards.
+    pub fn merge_buffer(&mut self, source: &str, target: &str) -> Result<usize, Error> {
+        if source == target {
+            return Err(Error::MergeIntoItself { buffer: source.to_string() });
         }
+        validate_buffer_name(target)?;
 
-        Ok(self.generate_combined_content())
+        let files = self.buffer(source)?.copied_files.clone();
+        let preserve_position = self.preserve_position_on_recopy;
+        let buffer = self.buffers.entry(target.to_string()).or_default();
+
+        buffer.record(format!("merge {}", source));
+        for copied_file in files {
+            buffer.upsert(copied_file, preserve_position);
+        }
+
+        Ok(buffer.len())
+    }
+
+    pub fn clear_buffer(&mut self, name: &str) -> Result<(), Error> {
+        let buffer = self
+            .buffers
+            .get_mut(name)
+            .ok_or_else(|| Error::BufferNotFound { buffer: name.to_string() })?;
+        buffer.clear();
+        Ok(())
     }
 
     pub fn clear(&mut self) {
-        self.copied_files.clear();
+        self.active_mut().clear();
     }
 
     pub fn generate_combined_content(&self) -> String {
-        self.copied_files
-            .iter()
-            .map(|f| format!("{}\n\n{}", f.display_path, f.content))
-            .collect::<Vec<_>>()
-            .join("\n\n---\n\n")
+        self.render(&PlainFormatter)
+    }
+
+    pub fn render(&self, formatter: &dyn OutputFormatter) -> String {
+        self.active().render(formatter)
     }
 
     pub fn get_file_count(&self) -> usize {
-        self.copied_files.len()
+        self.active().len()
     }
 
     pub fn has_files(&self) -> bool {
-        !self.copied_files.is_empty()
+        !self.active().is_empty()
     }
 
     pub fn get_files(&self) -> &Vec<CopiedFile> {
-        &self.copied_files
+        &self.active().copied_files
     }
 
     pub fn remove_file(&mut self, base_path: &str) -> bool {
-        let initial_len = self.copied_files.len();
-        self.copied_files.retain(|f| f.base_path != base_path);
-        self.copied_files.len() != initial_len
+        self.active_mut().remove_file(base_path)
     }
 
     pub fn contains_file(&self, base_path: &str) -> bool {
-        self.copied_files.iter().any(|f| f.base_path == base_path)
+        self.active().contains_file(base_path)
     }
 
     pub fn get_status_message(&self) -> String {
-        let count = self.copied_files.len();
-        if count == 0 {
+        let count = self.get_file_count();
+        let message = if count == 0 {
             "No files copied".to_string()
         } else if count == 1 {
-            format!("1 file copied")
+            "1 file copied".to_string()
         } else {
             format!("{} files copied", count)
+        };
+
+        if self.active_buffer == DEFAULT_BUFFER {
+            message
+        } else {
+            format!("{} in buffer '{}'", message, self.active_buffer)
         }
     }
 }
 
+fn validate_buffer_name(name: &str) -> Result<(), Error> {
+    let reason = if name.is_empty() {
+        "cannot be empty"
+    } else if name.chars().any(char::is_whitespace) {
+        "cannot contain whitespace"
+    } else {
+        return Ok(());
+    };
+    Err(Error::InvalidBufferName {
+        name: name.to_string(),
+        reason,
+    })
+}
+
 impl Default for ClipboardManager {
     fn default() -> Self {
         Self::new()
@@ -125,7 +249,9 @@ impl Default for ClipboardManager {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::paths::{DisplayMode, Workspace};
     use crate::models::FileSelection;
+    use chrono::DateTime;
 
     #[test]
     fn test_copy_file_with_content() {
@@ -196,4 +322,297 @@ mod tests {
         let files = clipboard.get_files();
         assert_eq!(files[0].content, "new content");
     }
+
+    #[test]
+    fn test_multiple_selections_same_file() {
+        let mut clipboard = ClipboardManager::new();
+        clipboard.copy_file_with_content(
+            "test.rs".to_string(),
+            String::new(),
+            Some(FileSelection::new(10, 11, "ten\neleven".to_string())),
+        ).unwrap();
+        clipboard.copy_file_with_content(
+            "test.rs".to_string(),
+            String::new(),
+            Some(FileSelection::new(80, 80, "eighty".to_string())),
+        ).unwrap();
+
+        assert_eq!(clipboard.get_file_count(), 1);
+        let files = clipboard.get_files();
+        assert_eq!(files[0].selections.len(), 2);
+        assert_eq!(
+            clipboard.generate_combined_content(),
+            "test.rs:10-11,80\n\nten\neleven\n…\neighty"
+        );
+    }
+
+    #[test]
+    fn test_overlapping_selections_merge() {
+        let mut clipboard = ClipboardManager::new();
+        clipboard.copy_file_with_content(
+            "test.rs".to_string(),
+            String::new(),
+            Some(FileSelection::new(1, 2, "one\ntwo".to_string())),
+        ).unwrap();
+        clipboard.copy_file_with_content(
+            "test.rs".to_string(),
+            String::new(),
+            Some(FileSelection::new(2, 3, "two\nthree".to_string())),
+        ).unwrap();
+
+        let files = clipboard.get_files();
+        assert_eq!(files[0].selections.len(), 1);
+        assert_eq!(files[0].display_path, "test.rs:1-3");
+        assert_eq!(files[0].content, "one\ntwo\nthree");
+    }
+
+    #[test]
+    fn test_whole_file_replaces_selections() {
+        let mut clipboard = ClipboardManager::new();
+        clipboard.copy_file_with_content(
+            "test.rs".to_string(),
+            String::new(),
+            Some(FileSelection::new(1, 1, "one".to_string())),
+        ).unwrap();
+        clipboard.copy_file_with_content(
+            "test.rs".to_string(),
+            "whole file".to_string(),
+            None,
+        ).unwrap();
+
+        let files = clipboard.get_files();
+        assert_eq!(files.len(), 1);
+        assert!(!files[0].is_partial());
+        assert_eq!(files[0].content, "whole file");
+    }
+
+    fn clipboard_with(paths: &[&str]) -> ClipboardManager {
+        let mut clipboard = ClipboardManager::new();
+        for path in paths {
+            clipboard.copy_file_with_content(
+                path.to_string(),
+                "content".to_string(),
+                None,
+            ).unwrap();
+        }
+        clipboard
+    }
+
+    fn paths(clipboard: &ClipboardManager) -> Vec<&str> {
+        clipboard.get_files().iter().map(|f| f.base_path.as_str()).collect()
+    }
+
+    #[test]
+    fn test_recopy_moves_to_end_by_default() {
+        let mut clipboard = clipboard_with(&["a.rs", "b.rs", "c.rs"]);
+        clipboard.copy_file_with_content("a.rs".to_string(), "new".to_string(), None).unwrap();
+
+        assert_eq!(paths(&clipboard), vec!["b.rs", "c.rs", "a.rs"]);
+    }
+
+    #[test]
+    fn test_recopy_preserves_position() {
+        let mut clipboard = clipboard_with(&["a.rs", "b.rs", "c.rs"]);
+        clipboard.preserve_position_on_recopy = true;
+        clipboard.copy_file_with_content("a.rs".to_string(), "new".to_string(), None).unwrap();
+
+        assert_eq!(paths(&clipboard), vec!["a.rs", "b.rs", "c.rs"]);
+        assert_eq!(clipboard.get_files()[0].content, "new");
+    }
+
+    #[test]
+    fn test_move_entry() {
+        let mut clipboard = clipboard_with(&["a.rs", "b.rs", "c.rs"]);
+
+        clipboard.active_mut().move_entry("c.rs", 1).unwrap();
+        assert_eq!(paths(&clipboard), vec!["c.rs", "a.rs", "b.rs"]);
+
+        clipboard.active_mut().move_entry("1", 3).unwrap();
+        assert_eq!(paths(&clipboard), vec!["a.rs", "b.rs", "c.rs"]);
+
+        // Moving an entry to where it already is records no undo step
+        let steps = clipboard.active().history().count();
+        let moved = clipboard.active_mut().move_entry("b.rs", 2).unwrap();
+        assert_eq!(moved.position, 2);
+        assert_eq!(clipboard.active().history().count(), steps);
+
+        assert_eq!(
+            clipboard.active_mut().move_entry("a.rs", 4),
+            Err(Error::PositionOutOfRange { position: 4, len: 3 })
+        );
+        assert!(matches!(
+            clipboard.active_mut().move_entry("missing.rs", 1),
+            Err(Error::EntryNotInClipboard { .. })
+        ));
+    }
+
+    #[test]
+    fn test_move_entry_up_and_down() {
+        let mut clipboard = clipboard_with(&["a.rs", "b.rs", "c.rs"]);
+
+        clipboard.active_mut().move_entry_up("b.rs").unwrap();
+        assert_eq!(paths(&clipboard), vec!["b.rs", "a.rs", "c.rs"]);
+
+        clipboard.active_mut().move_entry_down("b.rs").unwrap();
+        assert_eq!(paths(&clipboard), vec!["a.rs", "b.rs", "c.rs"]);
+
+        assert!(matches!(clipboard.active_mut().move_entry_up("a.rs"), Err(Error::EntryAlreadyFirst { .. })));
+        assert!(matches!(clipboard.active_mut().move_entry_down("c.rs"), Err(Error::EntryAlreadyLast { .. })));
+    }
+
+    #[test]
+    fn test_sort_and_reverse() {
+        let mut clipboard = clipboard_with(&["c.rs", "a.rs", "b.rs"]);
+        clipboard.active_mut().copied_files[0].copied_at = DateTime::from_timestamp(300, 0).unwrap();
+        clipboard.active_mut().copied_files[1].copied_at = DateTime::from_timestamp(100, 0).unwrap();
+        clipboard.active_mut().copied_files[2].copied_at = DateTime::from_timestamp(200, 0).unwrap();
+
+        clipboard.active_mut().sort_by_path();
+        assert_eq!(paths(&clipboard), vec!["a.rs", "b.rs", "c.rs"]);
+
+        clipboard.active_mut().reverse();
+        assert_eq!(paths(&clipboard), vec!["c.rs", "b.rs", "a.rs"]);
+
+        clipboard.active_mut().sort_by_copy_time();
+        assert_eq!(paths(&clipboard), vec!["a.rs", "b.rs", "c.rs"]);
+    }
+
+    #[test]
+    fn test_undo_clear() {
+        let mut clipboard = clipboard_with(&["a.rs", "b.rs"]);
+        clipboard.clear();
+        assert_eq!(clipboard.get_file_count(), 0);
+
+        clipboard.active_mut().undo().unwrap();
+        assert_eq!(paths(&clipboard), vec!["a.rs", "b.rs"]);
+
+        clipboard.active_mut().redo().unwrap();
+        assert_eq!(clipboard.get_file_count(), 0);
+    }
+
+    #[test]
+    fn test_undo_range_replacement() {
+        let mut clipboard = ClipboardManager::new();
+        clipboard.copy_file_with_content(
+            "test.rs".to_string(),
+            String::new(),
+            Some(FileSelection::new(1, 2, "one\ntwo".to_string())),
+        ).unwrap();
+        clipboard.copy_file_with_content("test.rs".to_string(), "whole".to_string(), None).unwrap();
+
+        clipboard.active_mut().undo().unwrap();
+        assert_eq!(clipboard.get_files()[0].display_path, "test.rs:1-2");
+    }
+
+    #[test]
+    fn test_undo_with_empty_history() {
+        let mut clipboard = ClipboardManager::new();
+        assert_eq!(clipboard.active_mut().undo(), Err(Error::NothingToUndo));
+        assert_eq!(clipboard.active_mut().redo(), Err(Error::NothingToRedo));
+    }
+
+    #[test]
+    fn test_history_and_restore() {
+        let mut clipboard = clipboard_with(&["a.rs", "b.rs", "c.rs"]);
+
+        let counts: Vec<_> = clipboard.active().history().map(|s| s.files.len()).collect();
+        assert_eq!(counts, vec![2, 1, 0]);
+
+        clipboard.active_mut().restore_snapshot(2).unwrap();
+        assert_eq!(paths(&clipboard), vec!["a.rs"]);
+        assert_eq!(clipboard.active_mut().restore_snapshot(10), Err(Error::HistoryEntryNotFound { index: 10 }));
+
+        clipboard.active_mut().undo().unwrap();
+        assert_eq!(paths(&clipboard), vec!["a.rs", "b.rs", "c.rs"]);
+    }
+
+    #[test]
+    fn test_copy_to_named_buffer_keeps_active() {
+        let mut clipboard = clipboard_with(&["a.rs"]);
+        clipboard.copy_file_to_buffer(
+            "bug",
+            "b.rs".to_string(),
+            "content".to_string(),
+            None,
+        ).unwrap();
+
+        assert_eq!(clipboard.active_buffer, DEFAULT_BUFFER);
+        assert_eq!(paths(&clipboard), vec!["a.rs"]);
+        assert_eq!(clipboard.buffer("bug").unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_switch_and_list_buffers() {
+        let mut clipboard = clipboard_with(&["a.rs"]);
+        clipboard.switch_buffer("refactor").unwrap();
+        clipboard.copy_file_with_content("b.rs".to_string(), "x".to_string(), None).unwrap();
+        clipboard.copy_file_with_content("c.rs".to_string(), "y".to_string(), None).unwrap();
+
+        assert_eq!(
+            clipboard.list_buffers(),
+            vec![("default", 1, false), ("refactor", 2, true)]
+        );
+        assert_eq!(clipboard.get_status_message(), "2 files copied in buffer 'refactor'");
+
+        clipboard.switch_buffer(DEFAULT_BUFFER).unwrap();
+        assert_eq!(paths(&clipboard), vec!["a.rs"]);
+        assert!(matches!(clipboard.switch_buffer("has space"), Err(Error::InvalidBufferName { .. })));
+    }
+
+    #[test]
+    fn test_merge_buffer() {
+        let mut clipboard = clipboard_with(&["a.rs", "b.rs"]);
+        clipboard.copy_file_to_buffer("bug", "b.rs".to_string(), "bug b".to_string(), None).unwrap();
+        clipboard.copy_file_to_buffer("bug", "c.rs".to_string(), "bug c".to_string(), None).unwrap();
+
+        clipboard.merge_buffer("bug", DEFAULT_BUFFER).unwrap();
+        assert_eq!(paths(&clipboard), vec!["a.rs", "b.rs", "c.rs"]);
+        assert_eq!(clipboard.get_files()[1].content, "bug b");
+        assert_eq!(clipboard.buffer("bug").unwrap().len(), 2);
+
+        clipboard.active_mut().undo().unwrap();
+        assert_eq!(paths(&clipboard), vec!["a.rs", "b.rs"]);
+
+        assert!(matches!(clipboard.merge_buffer("missing", DEFAULT_BUFFER), Err(Error::BufferNotFound { .. })));
+        assert!(matches!(clipboard.merge_buffer("bug", "bug"), Err(Error::MergeIntoItself { .. })));
+    }
+
+    #[test]
+    fn test_clear_single_buffer() {
+        let mut clipboard = clipboard_with(&["a.rs"]);
+        clipboard.copy_file_to_buffer("bug", "b.rs".to_string(), "x".to_string(), None).unwrap();
+
+        clipboard.clear_buffer("bug").unwrap();
+        assert_eq!(clipboard.buffer("bug").unwrap().len(), 0);
+        assert_eq!(clipboard.get_file_count(), 1);
+        assert_eq!(clipboard.clear_buffer("missing"), Err(Error::BufferNotFound { buffer: "missing".to_string() }));
+    }
+
+    #[test]
+    fn test_refresh_rereads_changed_files() {
+        let dir = std::env::temp_dir().join(format!("copy-path-refresh-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("main.rs"), "fn main() {}").unwrap();
+        let workspace = Workspace::new(&dir.to_string_lossy(), false, DisplayMode::Relative);
+
+        let mut clipboard = ClipboardManager::new();
+        clipboard.copy_file_with_content("main.rs".to_string(), "fn main() {}".to_string(), None).unwrap();
+        clipboard.copy_file_with_content("gone.rs".to_string(), "old".to_string(), None).unwrap();
+        fs::write(dir.join("main.rs"), "fn main() { run(); }").unwrap();
+
+        let statuses = clipboard.active().check_staleness(&workspace);
+        assert_eq!(statuses[0].1, EntryStatus::Changed);
+        assert_eq!(statuses[1].1, EntryStatus::Missing);
+
+        let results = clipboard.active_mut().refresh(&workspace);
+        assert_eq!(results[0].1, EntryStatus::Changed);
+        assert_eq!(clipboard.get_files()[0].content, "fn main() { run(); }");
+        assert_eq!(clipboard.get_files()[1].content, "old");
+        assert_eq!(clipboard.active().check_staleness(&workspace)[0].1, EntryStatus::Fresh);
+
+        clipboard.active_mut().undo().unwrap();
+        assert_eq!(clipboard.get_files()[0].content, "fn main() {}");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
 }
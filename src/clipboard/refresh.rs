@@ -0,0 +1,130 @@
+use crate::models::CopiedFile;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EntryStatus {
+    Fresh,
+    Changed,
+    Missing,
+    Unreadable(String),
+    // A selected range could not be found confidently in the changed file
+    RangeNotFound(String),
+}
+
+impl EntryStatus {
+    pub fn is_stale(&self) -> bool {
+        *self != EntryStatus::Fresh
+    }
+
+    // Stable identifier of the status, without the details `flag` adds
+    pub fn name(&self) -> &'static str {
+        match self {
+            EntryStatus::Fresh => "fresh",
+            EntryStatus::Changed => "changed",
+            EntryStatus::Missing => "missing",
+            EntryStatus::Unreadable(_) => "unreadable",
+            EntryStatus::RangeNotFound(_) => "range_not_found",
+        }
+    }
+
+    pub fn flag(&self) -> String {
+        match self {
+            EntryStatus::Fresh => "fresh".to_string(),
+            EntryStatus::Changed => "changed on disk".to_string(),
+            EntryStatus::Missing => "missing on disk".to_string(),
+            EntryStatus::Unreadable(error) => format!("unreadable: {}", error),
+            EntryStatus::RangeNotFound(reason) => format!("changed on disk, {}", reason),
+        }
+    }
+}
+
+// Where a refreshed entry comes from; `Err` carries the status to report
+pub(super) fn read_source(path: &Path) -> Result<String, EntryStatus> {
+    fs::read_to_string(path).map_err(|error| match error.kind() {
+        ErrorKind::NotFound => EntryStatus::Missing,
+        _ => EntryStatus::Unreadable(error.to_string()),
+    })
+}
+
+// Compares the entry with what copying the file again would capture
+pub(super) fn check_entry(file: &CopiedFile, source: Result<String, EntryStatus>) -> EntryStatus {
+    recapture_entry(file, source).1
+}
+
+// The recaptured entry, when one could be built, and how it compares with the stored copy
+pub(super) fn recapture_entry(
+    file: &CopiedFile,
+    source: Result<String, EntryStatus>,
+) -> (Option<CopiedFile>, EntryStatus) {
+    let source = match source {
+        Ok(source) => source,
+        Err(status) => return (None, status),
+    };
+
+    match file.recapture(&source) {
+        Ok(recaptured)
+            if recaptured.captured_hash() == file.captured_hash()
+                && recaptured.display_path == file.display_path =>
+        {
+            (None, EntryStatus::Fresh)
+        }
+        Ok(recaptured) => (Some(recaptured), EntryStatus::Changed),
+        Err(reason) => (None, EntryStatus::RangeNotFound(reason)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::FileSelection;
+
+    fn partial(start_line: u32, end_line: u32, content: &str) -> CopiedFile {
+        CopiedFile::from_selection(
+            "a.rs".to_string(),
+            FileSelection::new(start_line, end_line, content.to_string()),
+        )
+    }
+
+    #[test]
+    fn test_unchanged_file_is_fresh() {
+        let file = CopiedFile::new("a.rs".to_string(), "a.rs".to_string(), "fn a() {}".to_string());
+        assert_eq!(check_entry(&file, Ok("fn a() {}".to_string())), EntryStatus::Fresh);
+
+        // Edits outside a selected range do not make it stale
+        let file = partial(2, 2, "two");
+        assert_eq!(check_entry(&file, Ok("ONE\ntwo\nTHREE".to_string())), EntryStatus::Fresh);
+    }
+
+    #[test]
+    fn test_changed_file_is_recaptured() {
+        let file = partial(2, 3, "two\nthree");
+        let (recaptured, status) = recapture_entry(&file, Ok("zero\none\ntwo\nthree".to_string()));
+
+        // Same text, new lines
+        assert_eq!(status, EntryStatus::Changed);
+        assert_eq!(recaptured.unwrap().display_path, "a.rs:3-4");
+    }
+
+    #[test]
+    fn test_trimmed_entry_compares_with_captured_hash() {
+        let mut file = CopiedFile::new("a.rs".to_string(), "a.rs".to_string(), "// c\nfn a() {}".to_string());
+        file.content = "fn a() {}".to_string();
+
+        assert_eq!(check_entry(&file, Ok("// c\nfn a() {}".to_string())), EntryStatus::Fresh);
+    }
+
+    #[test]
+    fn test_missing_and_lost_ranges() {
+        let file = partial(5, 6, "five\nsix");
+        assert_eq!(check_entry(&file, Err(EntryStatus::Missing)), EntryStatus::Missing);
+        assert!(matches!(
+            check_entry(&file, Ok("one".to_string())),
+            EntryStatus::RangeNotFound(_)
+        ));
+
+        let missing = std::env::temp_dir().join(format!("missing-{}.rs", std::process::id()));
+        assert_eq!(read_source(&missing), Err(EntryStatus::Missing));
+    }
+}
@@ -0,0 +1,544 @@
+use crate::language::detect_language;
+use crate::models::{CopiedFile, ELISION_MARKER};
+use crate::output::OutputFormatter;
+use crate::tokens::TokenEstimator;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SizeUnit {
+    Bytes,
+    Tokens(TokenEstimator),
+}
+
+// The unit a limit is given in; tokens are counted with the configured estimator
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LimitUnit {
+    #[default]
+    Tokens,
+    Bytes,
+}
+
+impl LimitUnit {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "tokens" => Ok(LimitUnit::Tokens),
+            "bytes" => Ok(LimitUnit::Bytes),
+            other => Err(format!("Unknown unit '{}' (expected 'tokens' or 'bytes')", other)),
+        }
+    }
+
+    pub fn size_unit(&self, estimator: TokenEstimator) -> SizeUnit {
+        match self {
+            LimitUnit::Tokens => SizeUnit::Tokens(estimator),
+            LimitUnit::Bytes => SizeUnit::Bytes,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SizeTarget {
+    pub limit: usize,
+    pub unit: SizeUnit,
+}
+
+impl SizeTarget {
+    pub fn new(limit: usize, unit: SizeUnit) -> Self {
+        Self { limit, unit }
+    }
+
+    pub fn measure(&self, text: &str) -> usize {
+        match self.unit {
+            SizeUnit::Bytes => text.len(),
+            SizeUnit::Tokens(estimator) => estimator.count(text),
+        }
+    }
+
+    pub fn unit_name(&self) -> &'static str {
+        match self.unit {
+            SizeUnit::Bytes => "bytes",
+            SizeUnit::Tokens(_) => "tokens",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrimStrategy {
+    // Removes whole entries, least recently copied first
+    DropOldest,
+    // Keeps the first and last lines of each file around an elision marker
+    HeadTail,
+    StripComments,
+    // Keeps only lines that declare functions, types and modules
+    Signatures,
+}
+
+impl TrimStrategy {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "drop-oldest" => Ok(TrimStrategy::DropOldest),
+            "head-tail" => Ok(TrimStrategy::HeadTail),
+            "strip-comments" => Ok(TrimStrategy::StripComments),
+            "signatures" => Ok(TrimStrategy::Signatures),
+            other => Err(format!(
+                "Unknown trim strategy '{}' (expected drop-oldest, head-tail, strip-comments or signatures)",
+                other
+            )),
+        }
+    }
+
+    // Comma separated, applied in the given order
+    pub fn parse_list(value: &str) -> Result<Vec<Self>, String> {
+        value
+            .split(',')
+            .filter(|part| !part.trim().is_empty())
+            .map(Self::parse)
+            .collect()
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            TrimStrategy::DropOldest => "drop-oldest",
+            TrimStrategy::HeadTail => "head-tail",
+            TrimStrategy::StripComments => "strip-comments",
+            TrimStrategy::Signatures => "signatures",
+        }
+    }
+
+    pub fn defaults() -> Vec<Self> {
+        vec![TrimStrategy::StripComments, TrimStrategy::HeadTail]
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct FitReport {
+    pub target: SizeTarget,
+    pub before: usize,
+    pub after: usize,
+    // One line per change, in the order it was made
+    pub cuts: Vec<String>,
+}
+
+impl FitReport {
+    pub fn fits(&self) -> bool {
+        self.after <= self.target.limit
+    }
+
+    pub fn summary(&self) -> String {
+        let unit = self.target.unit_name();
+        let mut lines = vec![if self.cuts.is_empty() && self.fits() {
+            format!("Already within {} {} ({} {})", self.target.limit, unit, self.after, unit)
+        } else if self.cuts.is_empty() {
+            format!(
+                "{} {} is over the limit of {} and nothing could be cut",
+                self.after, unit, self.target.limit
+            )
+        } else if self.fits() {
+            format!("Trimmed from {} to {} {} (limit {})", self.before, self.after, unit, self.target.limit)
+        } else {
+            format!(
+                "Trimmed from {} to {} {}, still over the limit of {}",
+                self.before, self.after, unit, self.target.limit
+            )
+        }];
+
+        lines.extend(self.cuts.iter().map(|cut| format!("- {}", cut)));
+        lines.join("\n")
+    }
+}
+
+// Applies `strategies` in order, each only as far as needed, until the rendered
+// output fits the target. Returns the trimmed entries and what was cut.
+pub fn fit_to_budget(
+    files: &[CopiedFile],
+    formatter: &dyn OutputFormatter,
+    target: SizeTarget,
+    strategies: &[TrimStrategy],
+) -> (Vec<CopiedFile>, FitReport) {
+    let measure = |files: &[CopiedFile]| target.measure(&formatter.render(files));
+
+    let mut files = files.to_vec();
+    let before = measure(&files);
+    let mut report = FitReport {
+        target,
+        before,
+        after: before,
+        cuts: Vec::new(),
+    };
+
+    for strategy in strategies {
+        if report.after <= target.limit {
+            break;
+        }
+
+        match strategy {
+            TrimStrategy::DropOldest => drop_oldest(&mut files, &measure, target.limit, &mut report.cuts),
+            TrimStrategy::HeadTail => head_tail(&mut files, &measure, target.limit, &mut report.cuts),
+            TrimStrategy::StripComments => {
+                rewrite_largest_first(&mut files, &measure, target.limit, &mut report.cuts, |file| {
+                    let language = detect_language(&file.base_path, &file.content);
+                    let mut removed = 0;
+                    let changed = file.trim_with(|text| {
+                        let (content, count) = strip_comments(language, text);
+                        removed += count;
+                        (count > 0).then_some(content)
+                    });
+                    changed.then(|| format!("removed {} comment and blank lines", removed))
+                })
+            }
+            TrimStrategy::Signatures => {
+                rewrite_largest_first(&mut files, &measure, target.limit, &mut report.cuts, |file| {
+                    let (mut kept, mut total) = (0, 0);
+                    let changed = file.trim_with(|text| {
+                        let lines = text.lines().count();
+                        let content = signatures_only(text);
+                        let signatures = content.lines().count();
+                        total += lines;
+                        if signatures > 0 && signatures < lines {
+                            kept += signatures;
+                            Some(content)
+                        } else {
+                            kept += lines;
+                            None
+                        }
+                    });
+                    changed.then(|| format!("kept {} signature lines of {}", kept, total))
+                })
+            }
+        }
+
+        report.after = measure(&files);
+    }
+
+    (files, report)
+}
+
+// Keeps at least one entry; the remaining strategies can shrink it further
+fn drop_oldest(
+    files: &mut Vec<CopiedFile>,
+    measure: &dyn Fn(&[CopiedFile]) -> usize,
+    limit: usize,
+    cuts: &mut Vec<String>,
+) {
+    while files.len() > 1 && measure(files) > limit {
+        let oldest = files
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, file)| file.copied_at)
+            .map(|(index, _)| index)
+            .unwrap_or(0);
+        let dropped = files.remove(oldest);
+        cuts.push(format!("dropped {}", dropped.display_path));
+    }
+}
+
+// Rewrites one file at a time, biggest first, until the output fits
+fn rewrite_largest_first(
+    files: &mut [CopiedFile],
+    measure: &dyn Fn(&[CopiedFile]) -> usize,
+    limit: usize,
+    cuts: &mut Vec<String>,
+    mut rewrite: impl FnMut(&mut CopiedFile) -> Option<String>,
+) {
+    let mut order: Vec<usize> = (0..files.len()).collect();
+    order.sort_by_key(|&index| std::cmp::Reverse(files[index].content.len()));
+
+    for index in order {
+        if measure(files) <= limit {
+            return;
+        }
+        if let Some(description) = rewrite(&mut files[index]) {
+            cuts.push(format!("{}: {}", files[index].display_path, description));
+        }
+    }
+}
+
+// Finds the largest number of head and tail lines per file that still fits,
+// since the rendered size only grows with it
+fn head_tail(
+    files: &mut Vec<CopiedFile>,
+    measure: &dyn Fn(&[CopiedFile]) -> usize,
+    limit: usize,
+    cuts: &mut Vec<String>,
+) {
+    let longest = files.iter().map(|f| f.content.lines().count()).max().unwrap_or(0);
+    let truncated = |keep: usize| -> Vec<CopiedFile> {
+        files.iter().map(|file| truncate_entry(file, keep).0).collect()
+    };
+
+    // `keep` lines at each end; 1 is the most aggressive setting
+    let (mut low, mut high) = (1, longest / 2);
+    if high < low {
+        return;
+    }
+    while low < high {
+        let mid = (low + high).div_ceil(2);
+        if measure(&truncated(mid)) <= limit {
+            low = mid;
+        } else {
+            high = mid - 1;
+        }
+    }
+
+    let mut result = Vec::with_capacity(files.len());
+    for file in files.iter() {
+        let (trimmed, omitted) = truncate_entry(file, low);
+        if omitted > 0 {
+            cuts.push(format!(
+                "{}: kept the first and last {} lines, omitted {}",
+                file.display_path, low, omitted
+            ));
+        }
+        result.push(trimmed);
+    }
+    *files = result;
+}
+
+// The entry cut down to `keep` lines at each end, and how many lines were omitted
+fn truncate_entry(file: &CopiedFile, keep: usize) -> (CopiedFile, usize) {
+    let mut file = file.clone();
+    let mut omitted = 0;
+    file.trim_with(|text| {
+        let content = truncate_head_tail(text, keep)?;
+        omitted += text.lines().count() - 2 * keep;
+        Some(content)
+    });
+    (file, omitted)
+}
+
+fn truncate_head_tail(content: &str, keep: usize) -> Option<String> {
+    let lines: Vec<&str> = content.lines().collect();
+    // Eliding a single line would not save anything
+    if lines.len() <= 2 * keep + 1 {
+        return None;
+    }
+
+    let omitted = lines.len() - 2 * keep;
+    let mut kept: Vec<String> = lines[..keep].iter().map(|line| line.to_string()).collect();
+    kept.push(format!("{} {} lines omitted {}", ELISION_MARKER, omitted, ELISION_MARKER));
+    kept.extend(lines[lines.len() - keep..].iter().map(|line| line.to_string()));
+    Some(kept.join("\n"))
+}
+
+fn comment_syntax(language: Option<&str>) -> (&'static [&'static str], Option<(&'static str, &'static str)>) {
+    match language {
+        Some(
+            "rust" | "javascript" | "jsx" | "typescript" | "tsx" | "go" | "java" | "kotlin" | "scala"
+            | "swift" | "c" | "cpp" | "csharp" | "dart" | "zig" | "groovy" | "protobuf" | "scss"
+            | "less" | "jsonc",
+        ) => (&["//"], Some(("/*", "*/"))),
+        Some("php") => (&["//", "#"], Some(("/*", "*/"))),
+        Some("css") => (&[], Some(("/*", "*/"))),
+        Some(
+            "python" | "ruby" | "perl" | "sh" | "bash" | "zsh" | "fish" | "powershell" | "r" | "julia"
+            | "elixir" | "yaml" | "toml" | "dockerfile" | "makefile" | "cmake" | "nim" | "hcl"
+            | "nix" | "gitignore" | "graphql",
+        ) => (&["#"], None),
+        Some("sql" | "lua" | "haskell") => (&["--"], None),
+        Some("erlang" | "latex") => (&["%"], None),
+        Some("clojure" | "ini") => (&[";"], None),
+        Some("fsharp" | "ocaml") => (&["//"], Some(("(*", "*)"))),
+        Some("html" | "xml" | "markdown" | "vue" | "svelte") => (&[], Some(("<!--", "-->"))),
+        _ => (&[], None),
+    }
+}
+
+// Only whole-line comments are removed; trailing comments may sit inside strings
+fn strip_comments(language: Option<&str>, content: &str) -> (String, usize) {
+    let (line_prefixes, block) = comment_syntax(language);
+    let mut kept = Vec::new();
+    let mut removed = 0;
+    let mut in_block = false;
+
+    for (index, line) in content.lines().enumerate() {
+        let trimmed = line.trim();
+
+        let is_comment = if in_block {
+            if let Some((_, close)) = block {
+                in_block = !trimmed.contains(close);
+            }
+            true
+        } else if let Some((open, close)) = block.filter(|(open, _)| trimmed.starts_with(open)) {
+            in_block = !trimmed[open.len()..].contains(close);
+            true
+        } else {
+            // A shebang is not a comment, even where `#` starts one
+            !(index == 0 && trimmed.starts_with("#!"))
+                && line_prefixes.iter().any(|prefix| trimmed.starts_with(prefix))
+        };
+
+        if is_comment || trimmed.is_empty() {
+            removed += 1;
+        } else {
+            kept.push(line);
+        }
+    }
+
+    (kept.join("\n"), removed)
+}
+
+const SIGNATURE_MODIFIERS: &[&str] = &[
+    "pub", "pub(crate)", "pub(super)", "export", "default", "async", "static", "public",
+    "private", "protected", "internal", "abstract", "final", "override", "unsafe", "const",
+    "extern", "virtual", "open", "sealed", "data", "inline",
+];
+
+const SIGNATURE_KEYWORDS: &[&str] = &[
+    "fn", "def", "class", "struct", "enum", "trait", "impl", "interface", "type", "func",
+    "function", "mod", "module", "protocol", "object", "record", "union", "macro_rules!",
+];
+
+fn is_signature(line: &str) -> bool {
+    let mut words = line.split_whitespace();
+    let first = words.find(|word| !SIGNATURE_MODIFIERS.contains(word));
+    // `fn name(` and `function name(` put the keyword before the name
+    first.is_some_and(|word| {
+        SIGNATURE_KEYWORDS.contains(&word)
+            || SIGNATURE_KEYWORDS.iter().any(|keyword| {
+                word.strip_prefix(keyword).is_some_and(|rest| rest.starts_with(['(', '<', '*']))
+            })
+    })
+}
+
+fn signatures_only(content: &str) -> String {
+    content
+        .lines()
+        .filter(|line| is_signature(line))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{content_hash, FileSelection};
+    use crate::output::PlainFormatter;
+    use chrono::{Duration, Utc};
+
+    fn file(path: &str, content: &str, age_minutes: i64) -> CopiedFile {
+        let mut file = CopiedFile::new(path.to_string(), path.to_string(), content.to_string());
+        file.copied_at = Utc::now() - Duration::minutes(age_minutes);
+        file
+    }
+
+    fn numbered_lines(count: usize) -> String {
+        (1..=count).map(|n| format!("line {}", n)).collect::<Vec<_>>().join("\n")
+    }
+
+    fn bytes(limit: usize) -> SizeTarget {
+        SizeTarget::new(limit, SizeUnit::Bytes)
+    }
+
+    #[test]
+    fn test_parse_strategies() {
+        assert_eq!(
+            TrimStrategy::parse_list("strip-comments,drop-oldest").unwrap(),
+            vec![TrimStrategy::StripComments, TrimStrategy::DropOldest]
+        );
+        assert!(TrimStrategy::parse_list("head-tail,shrink").is_err());
+    }
+
+    #[test]
+    fn test_nothing_cut_when_already_within_budget() {
+        let files = vec![file("a.rs", "fn a() {}", 0)];
+        let (trimmed, report) = fit_to_budget(&files, &PlainFormatter, bytes(1000), &TrimStrategy::defaults());
+
+        assert_eq!(trimmed[0].content, "fn a() {}");
+        assert!(report.cuts.is_empty());
+        assert!(report.fits());
+    }
+
+    #[test]
+    fn test_drop_oldest_removes_least_recent_first() {
+        let files = vec![
+            file("new.rs", &"a".repeat(100), 1),
+            file("old.rs", &"b".repeat(100), 10),
+            file("mid.rs", &"c".repeat(100), 5),
+        ];
+        let (trimmed, report) = fit_to_budget(&files, &PlainFormatter, bytes(250), &[TrimStrategy::DropOldest]);
+
+        assert_eq!(trimmed.len(), 2);
+        assert!(trimmed.iter().all(|f| f.base_path != "old.rs"));
+        assert_eq!(report.cuts, vec!["dropped old.rs"]);
+        assert!(report.fits());
+    }
+
+    #[test]
+    fn test_head_tail_keeps_as_many_lines_as_fit() {
+        let files = vec![file("log.txt", &numbered_lines(100), 0)];
+        let (trimmed, report) = fit_to_budget(&files, &PlainFormatter, bytes(200), &[TrimStrategy::HeadTail]);
+
+        let content = &trimmed[0].content;
+        assert!(content.starts_with("line 1\n"));
+        assert!(content.ends_with("line 100"));
+        assert!(content.contains("lines omitted"));
+        assert!(report.fits());
+        assert_eq!(report.cuts.len(), 1);
+        assert!(report.cuts[0].starts_with("log.txt: kept the first and last"));
+    }
+
+    #[test]
+    fn test_strip_comments_by_language() {
+        let rust = "// header\nfn main() {\n\n    /* block\n       comment */\n    run(); // trailing\n}";
+        let (content, removed) = strip_comments(Some("rust"), rust);
+        assert_eq!(content, "fn main() {\n    run(); // trailing\n}");
+        assert_eq!(removed, 4);
+
+        let python = "#!/usr/bin/env python\n# comment\nprint('#')\n";
+        let (content, removed) = strip_comments(detect_language("run", python), python);
+        assert_eq!(content, "#!/usr/bin/env python\nprint('#')");
+        assert_eq!(removed, 1);
+    }
+
+    #[test]
+    fn test_signatures_only() {
+        let content = "pub struct Point {\n    x: i32,\n}\n\nimpl Point {\n    pub fn new() -> Self {\n        Self { x: 0 }\n    }\n}";
+        assert_eq!(signatures_only(content), "pub struct Point {\nimpl Point {\n    pub fn new() -> Self {");
+
+        assert!(is_signature("export default function App() {"));
+        assert!(is_signature("async def fetch(url):"));
+        assert!(is_signature("impl<T> Trait for T {"));
+        assert!(!is_signature("let function = 1;"));
+    }
+
+    #[test]
+    fn test_strategies_apply_in_order_and_stop_when_fitting() {
+        let commented = format!("// note\n{}", "x".repeat(50));
+        let files = vec![file("a.rs", &commented, 0), file("b.rs", &numbered_lines(50), 0)];
+        let strategies = [TrimStrategy::StripComments, TrimStrategy::DropOldest];
+
+        let budget = PlainFormatter.render(&files).len() - 5;
+        let (trimmed, report) = fit_to_budget(&files, &PlainFormatter, bytes(budget), &strategies);
+
+        assert_eq!(trimmed.len(), 2);
+        assert_eq!(report.cuts, vec!["a.rs: removed 1 comment and blank lines"]);
+    }
+
+    #[test]
+    fn test_reports_when_still_over_budget() {
+        let files = vec![file("a.txt", &"a".repeat(500), 0)];
+        let (_, report) = fit_to_budget(&files, &PlainFormatter, bytes(10), &[TrimStrategy::DropOldest]);
+
+        assert!(!report.fits());
+        assert!(report.summary().contains("over the limit of 10 and nothing could be cut"));
+
+        let files = vec![file("a.txt", &numbered_lines(100), 0)];
+        let (_, report) = fit_to_budget(&files, &PlainFormatter, bytes(10), &[TrimStrategy::HeadTail]);
+        assert!(report.summary().contains("still over the limit of 10"));
+    }
+
+    #[test]
+    fn test_later_selections_keep_the_cut() {
+        let partial = CopiedFile::from_selection("log.txt".to_string(), FileSelection::new(1, 100, numbered_lines(100)));
+        let (mut trimmed, report) = fit_to_budget(&[partial], &PlainFormatter, bytes(200), &[TrimStrategy::HeadTail]);
+        assert!(report.fits());
+        // Staleness is still judged against the text as captured
+        assert_eq!(trimmed[0].captured_hash(), content_hash(&numbered_lines(100)));
+
+        trimmed[0].add_selection(FileSelection::new(200, 201, "line 200\nline 201".to_string()));
+        let content = &trimmed[0].content;
+        assert!(content.contains("lines omitted"));
+        assert!(!content.contains("line 50\n"));
+        assert!(content.ends_with("line 200\nline 201"));
+        assert_eq!(trimmed[0].selections[0].content, numbered_lines(100));
+        assert!(PlainFormatter.render(&trimmed).len() < report.after + 50);
+    }
+}
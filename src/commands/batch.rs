@@ -0,0 +1,124 @@
+use super::Command;
+use crate::error::Error;
+use serde::{de, Deserialize, Deserializer};
+use serde_json::Value;
+
+// What a batch does after a step fails
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BatchMode {
+    #[default]
+    Stop,
+    Continue,
+}
+
+impl BatchMode {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "stop" => Ok(BatchMode::Stop),
+            "continue" => Ok(BatchMode::Continue),
+            other => Err(format!("Unknown batch mode '{}' (expected 'stop' or 'continue')", other)),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            BatchMode::Stop => "stop",
+            BatchMode::Continue => "continue",
+        }
+    }
+}
+
+// One command of a batch. `args` holds the positional arguments as a list, or
+// the named arguments as an object, exactly as the command takes them.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BatchStep {
+    pub command: String,
+    #[serde(default)]
+    pub args: Value,
+}
+
+impl BatchStep {
+    // Steps are parsed when they run, so a bad step fails like any other
+    pub fn parse(&self) -> Result<Command, Error> {
+        let command = match &self.args {
+            Value::Null => Command::parse(&self.command, Vec::new())?,
+            Value::Object(fields) => Command::from_fields(&self.command, fields.clone())?,
+            Value::Array(values) => {
+                let args = values
+                    .iter()
+                    .map(|value| match value {
+                        Value::String(text) => text.clone(),
+                        other => other.to_string(),
+                    })
+                    .collect();
+                Command::parse(&self.command, args)?
+            }
+            other => {
+                return Err(Error::invalid_argument(
+                    &self.command,
+                    "args",
+                    format!("expected a list or an object, got {}", other),
+                ))
+            }
+        };
+
+        if matches!(command, Command::Batch { .. }) {
+            return Err(Error::InvalidArguments {
+                command: self.command.clone(),
+                field: None,
+                message: "batches cannot be nested".to_string(),
+            });
+        }
+        Ok(command)
+    }
+}
+
+// A JSON array of steps, or its text
+pub(super) fn steps<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<BatchStep>, D::Error> {
+    let value = match Value::deserialize(deserializer)? {
+        Value::String(text) => serde_json::from_str(&text).map_err(de::Error::custom)?,
+        value => value,
+    };
+    Vec::<BatchStep>::deserialize(value).map_err(de::Error::custom)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn step(value: Value) -> BatchStep {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn test_steps_take_positional_or_named_arguments() {
+        let positional = step(json!({ "command": "create_folder", "args": ["Docs", "README.md"] }));
+        let named = step(json!({ "command": "create_folder", "args": { "name": "Docs", "files": ["README.md"] } }));
+        let expected = Command::CreateFolder {
+            name: "Docs".to_string(),
+            files: vec!["README.md".to_string()],
+        };
+        assert_eq!(positional.parse().unwrap(), expected);
+        assert_eq!(named.parse().unwrap(), expected);
+
+        assert_eq!(step(json!({ "command": "status" })).parse().unwrap(), Command::Status);
+        assert_eq!(
+            step(json!({ "command": "restore_clipboard", "args": [2] })).parse().unwrap(),
+            Command::RestoreClipboard { index: 2 }
+        );
+    }
+
+    #[test]
+    fn test_invalid_steps() {
+        let error = step(json!({ "command": "stauts" })).parse().unwrap_err();
+        assert_eq!(error.code(), "unknown_command");
+
+        let nested = step(json!({ "command": "batch", "args": ["[]"] })).parse().unwrap_err();
+        assert_eq!(nested.to_string(), "Invalid arguments for batch: batches cannot be nested");
+
+        let error = step(json!({ "command": "status", "args": "now" })).parse().unwrap_err();
+        assert_eq!(error.to_string(), "Invalid argument 'args' for status: expected a list or an object, got \"now\"");
+    }
+}
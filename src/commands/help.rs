@@ -0,0 +1,426 @@
+use super::SIGNATURES;
+
+// What `help` shows for a command. Arguments are written as in the positional
+// form: `<name>` is required, `[name]` optional, `--name=<value>` an option and
+// `--name` a flag; positional ones come first, in SIGNATURES order.
+pub struct CommandHelp {
+    pub name: &'static str,
+    pub summary: &'static str,
+    pub arguments: &'static [(&'static str, &'static str)],
+    pub examples: &'static [&'static str],
+}
+
+const FORMAT: (&str, &str) = ("--format=<name>", "Output format for this command: plain, markdown, xml or json");
+const TEMPLATE: (&str, &str) = ("--template=<name>", "Named template to render with instead of the output format");
+const BUFFER: (&str, &str) = ("--buffer=<name>", "Buffer to copy into instead of the active one");
+
+pub const COMMANDS: &[CommandHelp] = &[
+    CommandHelp {
+        name: "copy_path_with_content",
+        summary: "Copy a file, or a selection of it, to the clipboard",
+        arguments: &[
+            ("<file_path>", "Path of the file"),
+            ("<content>", "Content of the file"),
+            ("[start_line]", "First selected line, 1-based"),
+            ("[end_line]", "Last selected line; required with start_line"),
+            ("[selected_content]", "The selected text, when it differs from content"),
+            FORMAT,
+            TEMPLATE,
+            BUFFER,
+        ],
+        examples: &[
+            "copy_path_with_content src/lib.rs \"<content>\"",
+            "copy_path_with_content src/lib.rs \"<content>\" 10 25 -- --format=markdown",
+        ],
+    },
+    CommandHelp {
+        name: "copy_directory",
+        summary: "Copy every text file under a directory, honoring ignore files",
+        arguments: &[
+            ("<path>", "Directory to copy"),
+            ("--include=<globs>", "Comma-separated globs a file must match"),
+            ("--exclude=<globs>", "Comma-separated globs of files to leave out"),
+            ("--max-size=<bytes>", "Skip files larger than this"),
+            FORMAT,
+            TEMPLATE,
+            BUFFER,
+        ],
+        examples: &["copy_directory src --include=*.rs --exclude=*_test.rs"],
+    },
+    CommandHelp {
+        name: "clear_clipboard",
+        summary: "Remove every file from the active buffer",
+        arguments: &[],
+        examples: &["clear_clipboard"],
+    },
+    CommandHelp {
+        name: "list_clipboard",
+        summary: "List the files in the active buffer, in order",
+        arguments: &[],
+        examples: &["list_clipboard"],
+    },
+    CommandHelp {
+        name: "set_token_budget",
+        summary: "Warn about or refuse copies that exceed a token limit",
+        arguments: &[
+            ("<limit>", "Token limit, or off to remove it"),
+            ("[mode]", "warn (default) or refuse"),
+        ],
+        examples: &["set_token_budget 8000", "set_token_budget 8000 refuse", "set_token_budget off"],
+    },
+    CommandHelp {
+        name: "set_token_estimator",
+        summary: "Choose how tokens are estimated",
+        arguments: &[("<estimator>", "bpe or chars")],
+        examples: &["set_token_estimator chars"],
+    },
+    CommandHelp {
+        name: "fit_to_budget",
+        summary: "Trim the clipboard until its rendered output fits a limit",
+        arguments: &[
+            ("[limit]", "Size limit; defaults to the token budget"),
+            ("--unit=<unit>", "tokens (default) or bytes"),
+            ("--strategy=<list>", "Comma-separated strip-comments, head-tail, signatures, drop-oldest"),
+            FORMAT,
+            TEMPLATE,
+        ],
+        examples: &["fit_to_budget 4000", "fit_to_budget 20000 --unit=bytes --strategy=signatures,drop-oldest"],
+    },
+    CommandHelp {
+        name: "clipboard_status",
+        summary: "Show which copied entries changed or disappeared on disk",
+        arguments: &[],
+        examples: &["clipboard_status"],
+    },
+    CommandHelp {
+        name: "refresh_clipboard",
+        summary: "Re-read copied entries that changed on disk",
+        arguments: &[],
+        examples: &["refresh_clipboard"],
+    },
+    CommandHelp {
+        name: "token_report",
+        summary: "Show the largest files by token count and the total",
+        arguments: &[],
+        examples: &["token_report"],
+    },
+    CommandHelp {
+        name: "switch_buffer",
+        summary: "Make a buffer active, creating it if needed",
+        arguments: &[("<name>", "Buffer name")],
+        examples: &["switch_buffer review"],
+    },
+    CommandHelp {
+        name: "list_buffers",
+        summary: "List the buffers and their file counts",
+        arguments: &[],
+        examples: &["list_buffers"],
+    },
+    CommandHelp {
+        name: "merge_buffer",
+        summary: "Copy the files of one buffer into another",
+        arguments: &[("<source>", "Buffer to take files from"), ("<target>", "Buffer to add them to")],
+        examples: &["merge_buffer review default"],
+    },
+    CommandHelp {
+        name: "clear_buffer",
+        summary: "Remove every file from a buffer",
+        arguments: &[("<name>", "Buffer name")],
+        examples: &["clear_buffer review"],
+    },
+    CommandHelp {
+        name: "undo_clipboard",
+        summary: "Undo the last change to the active buffer; the history lasts for the session",
+        arguments: &[],
+        examples: &["undo_clipboard"],
+    },
+    CommandHelp {
+        name: "redo_clipboard",
+        summary: "Redo the last undone change",
+        arguments: &[],
+        examples: &["redo_clipboard"],
+    },
+    CommandHelp {
+        name: "clipboard_history",
+        summary: "List the recorded states of the active buffer",
+        arguments: &[],
+        examples: &["clipboard_history"],
+    },
+    CommandHelp {
+        name: "restore_clipboard",
+        summary: "Bring back a state from the history",
+        arguments: &[("<index>", "Entry number as shown by clipboard_history")],
+        examples: &["restore_clipboard 2"],
+    },
+    CommandHelp {
+        name: "move_clipboard_entry",
+        summary: "Move an entry to a position",
+        arguments: &[("<entry>", "Entry position or path"), ("<position>", "New 1-based position")],
+        examples: &["move_clipboard_entry src/lib.rs 1", "move_clipboard_entry 3 1"],
+    },
+    CommandHelp {
+        name: "move_clipboard_entry_up",
+        summary: "Move an entry one position up",
+        arguments: &[("<entry>", "Entry position or path")],
+        examples: &["move_clipboard_entry_up src/lib.rs"],
+    },
+    CommandHelp {
+        name: "move_clipboard_entry_down",
+        summary: "Move an entry one position down",
+        arguments: &[("<entry>", "Entry position or path")],
+        examples: &["move_clipboard_entry_down 1"],
+    },
+    CommandHelp {
+        name: "sort_clipboard",
+        summary: "Sort the active buffer",
+        arguments: &[("<key>", "path or time")],
+        examples: &["sort_clipboard path"],
+    },
+    CommandHelp {
+        name: "reverse_clipboard",
+        summary: "Reverse the order of the active buffer",
+        arguments: &[],
+        examples: &["reverse_clipboard"],
+    },
+    CommandHelp {
+        name: "set_preserve_position",
+        summary: "Keep re-copied files in place instead of moving them to the end",
+        arguments: &[("<enabled>", "on or off")],
+        examples: &["set_preserve_position on"],
+    },
+    CommandHelp {
+        name: "create_folder",
+        summary: "Create a folder, optionally with files",
+        arguments: &[("<name>", "Folder name"), ("[files...]", "Files to add")],
+        examples: &["create_folder Docs README.md CHANGELOG.md"],
+    },
+    CommandHelp {
+        name: "create_subfolder",
+        summary: "Create a folder inside another",
+        arguments: &[
+            ("<parent>", "Parent folder id or name"),
+            ("<name>", "Folder name"),
+            ("[files...]", "Files to add"),
+        ],
+        examples: &["create_subfolder Docs Guides docs/guide.md"],
+    },
+    CommandHelp {
+        name: "move_folder",
+        summary: "Move a folder under another, or to the top level",
+        arguments: &[("<folder>", "Folder id or name"), ("<parent>", "New parent, or root for the top level")],
+        examples: &["move_folder Guides root"],
+    },
+    CommandHelp {
+        name: "folder_tree",
+        summary: "Show the folders as a tree",
+        arguments: &[],
+        examples: &["folder_tree"],
+    },
+    CommandHelp {
+        name: "delete_folder",
+        summary: "Delete a folder",
+        arguments: &[("<folder>", "Folder id or name"), ("--recursive", "Also delete its subfolders")],
+        examples: &["delete_folder Docs", "delete_folder Docs --recursive"],
+    },
+    CommandHelp {
+        name: "rename_folder",
+        summary: "Rename a folder",
+        arguments: &[("<folder>", "Folder id or name"), ("<new_name>", "New name")],
+        examples: &["rename_folder Docs Documentation"],
+    },
+    CommandHelp {
+        name: "clone_folder",
+        summary: "Copy a folder under a new name",
+        arguments: &[("<folder>", "Folder id or name"), ("<new_name>", "Name of the copy")],
+        examples: &["clone_folder Docs \"Docs backup\""],
+    },
+    CommandHelp {
+        name: "import_folders",
+        summary: "Create folders from a JSON array of definitions",
+        arguments: &[("<folders>", "JSON array of objects with name, files, patterns and color")],
+        examples: &["import_folders '[{\"name\": \"Docs\", \"files\": [\"README.md\"]}]'"],
+    },
+    CommandHelp {
+        name: "add_file_to_folder",
+        summary: "Add a file to a folder",
+        arguments: &[("<folder>", "Folder id or name"), ("<file_path>", "File to add")],
+        examples: &["add_file_to_folder Docs README.md"],
+    },
+    CommandHelp {
+        name: "remove_file_from_folder",
+        summary: "Remove a file from a folder",
+        arguments: &[("<folder>", "Folder id or name"), ("<file_path>", "File to remove")],
+        examples: &["remove_file_from_folder Docs README.md"],
+    },
+    CommandHelp {
+        name: "add_pattern_to_folder",
+        summary: "Add an include glob, or an !exclude glob, to a folder",
+        arguments: &[("<folder>", "Folder id or name"), ("<pattern>", "Glob relative to the workspace root")],
+        examples: &["add_pattern_to_folder Sources src/**/*.rs", "add_pattern_to_folder Sources '!src/**/tests.rs'"],
+    },
+    CommandHelp {
+        name: "remove_pattern_from_folder",
+        summary: "Remove a glob from a folder",
+        arguments: &[("<folder>", "Folder id or name"), ("<pattern>", "Glob as it was added")],
+        examples: &["remove_pattern_from_folder Sources src/**/*.rs"],
+    },
+    CommandHelp {
+        name: "set_workspace_root",
+        summary: "Set the directory paths and patterns are relative to",
+        arguments: &[("<root>", "Directory, or off for the current directory")],
+        examples: &["set_workspace_root /home/me/project"],
+    },
+    CommandHelp {
+        name: "set_path_display",
+        summary: "Choose how paths are shown in copied output",
+        arguments: &[("<mode>", "relative, absolute or project")],
+        examples: &["set_path_display project"],
+    },
+    CommandHelp {
+        name: "set_resolve_symlinks",
+        summary: "Store paths through symlinks as their targets",
+        arguments: &[("<enabled>", "on or off")],
+        examples: &["set_resolve_symlinks on"],
+    },
+    CommandHelp {
+        name: "copy_folder_contents",
+        summary: "Copy the files of a folder",
+        arguments: &[
+            ("<folder>", "Folder id or name"),
+            ("--recursive", "Include the files of its subfolders"),
+            FORMAT,
+            TEMPLATE,
+        ],
+        examples: &["copy_folder_contents Docs", "copy_folder_contents Docs --recursive --format=xml"],
+    },
+    CommandHelp {
+        name: "set_output_format",
+        summary: "Choose the default output format",
+        arguments: &[("<format>", "plain, markdown, xml or json")],
+        examples: &["set_output_format markdown"],
+    },
+    CommandHelp {
+        name: "set_response_format",
+        summary: "Choose whether commands reply with text or a JSON envelope",
+        arguments: &[("<format>", "text or json")],
+        examples: &["set_response_format json"],
+    },
+    CommandHelp {
+        name: "define_template",
+        summary: "Define or replace a named output template",
+        arguments: &[("<template>", "JSON object with name, header, file, separator and footer")],
+        examples: &["define_template '{\"name\": \"brief\", \"file\": \"## {path}\\n{content}\"}'"],
+    },
+    CommandHelp {
+        name: "delete_template",
+        summary: "Delete a named template",
+        arguments: &[("<name>", "Template name")],
+        examples: &["delete_template brief"],
+    },
+    CommandHelp {
+        name: "list_templates",
+        summary: "List the named templates",
+        arguments: &[],
+        examples: &["list_templates"],
+    },
+    CommandHelp {
+        name: "list_folders",
+        summary: "List the folders with their files and pattern matches",
+        arguments: &[],
+        examples: &["list_folders"],
+    },
+    CommandHelp {
+        name: "status",
+        summary: "Summarize the clipboard and folders",
+        arguments: &[],
+        examples: &["status"],
+    },
+    CommandHelp {
+        name: "batch",
+        summary: "Run several commands in order in one call",
+        arguments: &[
+            ("<steps>", "JSON array of {\"command\": ..., \"args\": [...] or {...}}"),
+            ("--mode=<mode>", "stop (default) at the first failed step, or continue"),
+            ("--transactional", "Undo every change of the batch when a step fails"),
+        ],
+        examples: &[
+            "batch '[{\"command\": \"create_folder\", \"args\": [\"Docs\"]}, {\"command\": \"add_file_to_folder\", \"args\": [\"Docs\", \"README.md\"]}]'",
+            "batch '[...]' --mode=continue --transactional",
+        ],
+    },
+    CommandHelp {
+        name: "help",
+        summary: "List the commands, or describe one",
+        arguments: &[("[command]", "Command to describe")],
+        examples: &["help", "help copy_directory"],
+    },
+];
+
+impl CommandHelp {
+    pub fn usage(&self) -> String {
+        let mut usage = self.name.to_string();
+        for (argument, _) in self.arguments {
+            usage.push(' ');
+            usage.push_str(argument);
+        }
+        usage
+    }
+}
+
+pub fn help(name: &str) -> Option<&'static CommandHelp> {
+    COMMANDS.iter().find(|command| command.name == name)
+}
+
+// Commands within a few edits of `name`, closest first
+pub fn suggestions(name: &str) -> Vec<String> {
+    let max_distance = (name.chars().count() / 3).max(2);
+    let mut close: Vec<(usize, &str)> = SIGNATURES
+        .iter()
+        .map(|(command, _)| (strsim::osa_distance(name, command), *command))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .collect();
+    close.sort();
+    close.into_iter().take(3).map(|(_, command)| command.to_string()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_every_command_is_documented() {
+        for (name, positional) in SIGNATURES {
+            let help = help(name).unwrap_or_else(|| panic!("no help for {}", name));
+            let arguments: Vec<&str> = help.arguments.iter().map(|(argument, _)| *argument).collect();
+            for argument in positional.iter() {
+                let documented = match argument.strip_suffix('?') {
+                    Some(optional) => format!("[{}]", optional),
+                    None if argument.ends_with("...") => format!("[{}]", argument),
+                    None => format!("<{}>", argument),
+                };
+                assert!(
+                    arguments.contains(&documented.as_str()),
+                    "{} does not document '{}'",
+                    name,
+                    argument
+                );
+            }
+            assert!(!help.examples.is_empty(), "{} has no examples", name);
+        }
+        assert_eq!(COMMANDS.len(), SIGNATURES.len());
+    }
+
+    #[test]
+    fn test_usage() {
+        assert_eq!(help("delete_folder").unwrap().usage(), "delete_folder <folder> --recursive");
+        assert_eq!(help("status").unwrap().usage(), "status");
+    }
+
+    #[test]
+    fn test_suggestions() {
+        assert_eq!(suggestions("stauts"), vec!["status"]);
+        assert_eq!(suggestions("list_folder"), vec!["list_folders"]);
+        assert_eq!(suggestions("copy_dir"), Vec::<String>::new());
+        assert!(suggestions("undo_clipbaord").starts_with(&["undo_clipboard".to_string()]));
+    }
+}
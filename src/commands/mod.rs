@@ -0,0 +1,798 @@
+use crate::clipboard::{LimitUnit, SortKey, TrimStrategy};
+use crate::directory;
+use crate::error::Error;
+use crate::output::OutputFormat;
+use crate::paths::DisplayMode;
+use crate::response::ResponseFormat;
+use crate::templates::OutputTemplate;
+use crate::tokens::{BudgetMode, TokenEstimator};
+use serde::de::{self, DeserializeOwned, Deserializer};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::str::FromStr;
+
+mod batch;
+mod help;
+
+pub use batch::{BatchMode, BatchStep};
+pub use help::{help, CommandHelp, COMMANDS};
+
+// Every command and its named arguments. A command is invoked either with a
+// single JSON object argument naming them, or positionally in the order given
+// by SIGNATURES, followed by `--name=value` options and `--name` flags.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub enum Command {
+    CopyPathWithContent {
+        file_path: String,
+        content: String,
+        #[serde(default, deserialize_with = "optional_number")]
+        start_line: Option<u32>,
+        #[serde(default, deserialize_with = "optional_number")]
+        end_line: Option<u32>,
+        // The selected text, when it differs from `content`
+        #[serde(default)]
+        selected_content: Option<String>,
+        #[serde(default, deserialize_with = "optional_parsed")]
+        format: Option<OutputFormat>,
+        #[serde(default)]
+        template: Option<String>,
+        #[serde(default)]
+        buffer: Option<String>,
+    },
+    CopyDirectory {
+        path: String,
+        #[serde(default, deserialize_with = "glob_list")]
+        include: Vec<String>,
+        #[serde(default, deserialize_with = "glob_list")]
+        exclude: Vec<String>,
+        #[serde(default, deserialize_with = "optional_number")]
+        max_size: Option<u64>,
+        #[serde(default, deserialize_with = "optional_parsed")]
+        format: Option<OutputFormat>,
+        #[serde(default)]
+        template: Option<String>,
+        #[serde(default)]
+        buffer: Option<String>,
+    },
+    ClearClipboard,
+    ListClipboard,
+    SetTokenBudget {
+        // None for `off`
+        #[serde(deserialize_with = "number_or_off")]
+        limit: Option<usize>,
+        #[serde(default, deserialize_with = "optional_parsed")]
+        mode: Option<BudgetMode>,
+    },
+    SetTokenEstimator {
+        #[serde(deserialize_with = "parsed")]
+        estimator: TokenEstimator,
+    },
+    FitToBudget {
+        // Defaults to the token budget
+        #[serde(default, deserialize_with = "optional_number")]
+        limit: Option<usize>,
+        #[serde(default, deserialize_with = "optional_parsed")]
+        unit: Option<LimitUnit>,
+        #[serde(default, deserialize_with = "optional_parsed")]
+        strategy: Option<Vec<TrimStrategy>>,
+        #[serde(default, deserialize_with = "optional_parsed")]
+        format: Option<OutputFormat>,
+        #[serde(default)]
+        template: Option<String>,
+    },
+    ClipboardStatus,
+    RefreshClipboard,
+    TokenReport,
+    SwitchBuffer {
+        name: String,
+    },
+    ListBuffers,
+    MergeBuffer {
+        source: String,
+        target: String,
+    },
+    ClearBuffer {
+        name: String,
+    },
+    UndoClipboard,
+    RedoClipboard,
+    ClipboardHistory,
+    RestoreClipboard {
+        #[serde(deserialize_with = "number")]
+        index: usize,
+    },
+    MoveClipboardEntry {
+        // An index or a path
+        entry: String,
+        #[serde(deserialize_with = "number")]
+        position: usize,
+    },
+    MoveClipboardEntryUp {
+        entry: String,
+    },
+    MoveClipboardEntryDown {
+        entry: String,
+    },
+    SortClipboard {
+        #[serde(deserialize_with = "parsed")]
+        key: SortKey,
+    },
+    ReverseClipboard,
+    SetPreservePosition {
+        #[serde(deserialize_with = "switch")]
+        enabled: bool,
+    },
+    CreateFolder {
+        name: String,
+        #[serde(default)]
+        files: Vec<String>,
+    },
+    CreateSubfolder {
+        parent: String,
+        name: String,
+        #[serde(default)]
+        files: Vec<String>,
+    },
+    MoveFolder {
+        folder: String,
+        // A folder, or `root` for the top level
+        parent: String,
+    },
+    FolderTree,
+    DeleteFolder {
+        folder: String,
+        #[serde(default, deserialize_with = "switch")]
+        recursive: bool,
+    },
+    RenameFolder {
+        folder: String,
+        new_name: String,
+    },
+    CloneFolder {
+        folder: String,
+        new_name: String,
+    },
+    ImportFolders {
+        // A JSON array of folder definitions, or its text
+        #[serde(deserialize_with = "json")]
+        folders: Value,
+    },
+    AddFileToFolder {
+        folder: String,
+        file_path: String,
+    },
+    RemoveFileFromFolder {
+        folder: String,
+        file_path: String,
+    },
+    AddPatternToFolder {
+        folder: String,
+        pattern: String,
+    },
+    RemovePatternFromFolder {
+        folder: String,
+        pattern: String,
+    },
+    SetWorkspaceRoot {
+        // A directory, or `off` for the current directory
+        root: String,
+    },
+    SetPathDisplay {
+        #[serde(deserialize_with = "parsed")]
+        mode: DisplayMode,
+    },
+    SetResolveSymlinks {
+        #[serde(deserialize_with = "switch")]
+        enabled: bool,
+    },
+    CopyFolderContents {
+        folder: String,
+        #[serde(default, deserialize_with = "switch")]
+        recursive: bool,
+        #[serde(default, deserialize_with = "optional_parsed")]
+        format: Option<OutputFormat>,
+        #[serde(default)]
+        template: Option<String>,
+    },
+    SetOutputFormat {
+        #[serde(deserialize_with = "parsed")]
+        format: OutputFormat,
+    },
+    SetResponseFormat {
+        #[serde(deserialize_with = "parsed")]
+        format: ResponseFormat,
+    },
+    // The template definition itself is the argument object
+    DefineTemplate(OutputTemplate),
+    DeleteTemplate {
+        name: String,
+    },
+    ListTemplates,
+    ListFolders,
+    Status,
+    Help {
+        #[serde(default)]
+        command: Option<String>,
+    },
+    Batch {
+        #[serde(deserialize_with = "batch::steps")]
+        steps: Vec<BatchStep>,
+        #[serde(default, deserialize_with = "optional_parsed")]
+        mode: Option<BatchMode>,
+        // Undo every change of the batch when a step fails
+        #[serde(default, deserialize_with = "switch")]
+        transactional: bool,
+    },
+}
+
+// Positional argument names of every command, in order; a trailing `?` marks
+// an optional argument and a trailing `...` collects the remaining arguments
+// into a list
+pub const SIGNATURES: &[(&str, &[&str])] = &[
+    ("copy_path_with_content", &["file_path", "content", "start_line?", "end_line?", "selected_content?"]),
+    ("copy_directory", &["path"]),
+    ("clear_clipboard", &[]),
+    ("list_clipboard", &[]),
+    ("set_token_budget", &["limit", "mode?"]),
+    ("set_token_estimator", &["estimator"]),
+    ("fit_to_budget", &["limit?"]),
+    ("clipboard_status", &[]),
+    ("refresh_clipboard", &[]),
+    ("token_report", &[]),
+    ("switch_buffer", &["name"]),
+    ("list_buffers", &[]),
+    ("merge_buffer", &["source", "target"]),
+    ("clear_buffer", &["name"]),
+    ("undo_clipboard", &[]),
+    ("redo_clipboard", &[]),
+    ("clipboard_history", &[]),
+    ("restore_clipboard", &["index"]),
+    ("move_clipboard_entry", &["entry", "position"]),
+    ("move_clipboard_entry_up", &["entry"]),
+    ("move_clipboard_entry_down", &["entry"]),
+    ("sort_clipboard", &["key"]),
+    ("reverse_clipboard", &[]),
+    ("set_preserve_position", &["enabled"]),
+    ("create_folder", &["name", "files..."]),
+    ("create_subfolder", &["parent", "name", "files..."]),
+    ("move_folder", &["folder", "parent"]),
+    ("folder_tree", &[]),
+    ("delete_folder", &["folder"]),
+    ("rename_folder", &["folder", "new_name"]),
+    ("clone_folder", &["folder", "new_name"]),
+    ("import_folders", &["folders"]),
+    ("add_file_to_folder", &["folder", "file_path"]),
+    ("remove_file_from_folder", &["folder", "file_path"]),
+    ("add_pattern_to_folder", &["folder", "pattern"]),
+    ("remove_pattern_from_folder", &["folder", "pattern"]),
+    ("set_workspace_root", &["root"]),
+    ("set_path_display", &["mode"]),
+    ("set_resolve_symlinks", &["enabled"]),
+    ("copy_folder_contents", &["folder"]),
+    ("set_output_format", &["format"]),
+    ("set_response_format", &["format"]),
+    ("define_template", &[]),
+    ("delete_template", &["name"]),
+    ("list_templates", &[]),
+    ("list_folders", &[]),
+    ("status", &[]),
+    ("help", &["command?"]),
+    ("batch", &["steps"]),
+];
+
+impl Command {
+    pub fn parse(name: &str, args: Vec<String>) -> Result<Command, Error> {
+        let positional = signature(name).ok_or_else(|| unknown_command(name))?;
+
+        // A single argument is the JSON form only if it is a JSON object, so a
+        // name such as `{tmp}` is still taken positionally
+        let fields = match args.as_slice() {
+            [single] => match serde_json::from_str(single) {
+                Ok(Value::Object(fields)) => fields,
+                _ => positional_fields(name, positional, args)?,
+            },
+            _ => positional_fields(name, positional, args)?,
+        };
+        Self::from_fields(name, fields)
+    }
+
+    // Builds a command from arguments already named as in its schema
+    pub fn from_fields(name: &str, fields: Map<String, Value>) -> Result<Command, Error> {
+        if signature(name).is_none() {
+            return Err(unknown_command(name));
+        }
+
+        let no_arguments = fields.is_empty();
+        let mut tagged = Map::new();
+        tagged.insert(name.to_string(), Value::Object(fields));
+
+        let command = match serde_path_to_error::deserialize(Value::Object(tagged)) {
+            Ok(command) => command,
+            Err(error) => {
+                // Commands without arguments only deserialize from their name
+                match serde_json::from_value(Value::String(name.to_string())) {
+                    Ok(command) if no_arguments => command,
+                    Ok(_) => return Err(invalid_arguments(name, "takes no arguments")),
+                    Err(_) => return Err(describe_error(name, error)),
+                }
+            }
+        };
+        validate(name, &command)?;
+        Ok(command)
+    }
+
+    // Whether running the command can change the saved state
+    pub fn is_mutating(&self) -> bool {
+        !matches!(
+            self,
+            Command::ListClipboard
+                | Command::ClipboardStatus
+                | Command::TokenReport
+                | Command::ListBuffers
+                | Command::ClipboardHistory
+                | Command::FolderTree
+                | Command::ListTemplates
+                | Command::ListFolders
+                | Command::Status
+                | Command::Help { .. }
+        )
+    }
+}
+
+pub fn signature(name: &str) -> Option<&'static [&'static str]> {
+    SIGNATURES
+        .iter()
+        .find(|(command, _)| *command == name)
+        .map(|(_, positional)| *positional)
+}
+
+fn parse_bool(value: &str) -> Result<bool, String> {
+    match value.to_ascii_lowercase().as_str() {
+        "on" | "true" | "yes" | "1" => Ok(true),
+        "off" | "false" | "no" | "0" => Ok(false),
+        _ => Err(format!("Invalid value '{}' (expected on or off)", value)),
+    }
+}
+
+// Names positional arguments after the command's signature. Options are
+// passed as `--name=value` and flags as `--name`, with dashes in their names
+// standing for underscores. They are only read once every positional argument
+// has been given, so positional text that looks like an option stays what it
+// was; a bare `--` after the required arguments ends the positional ones early.
+fn positional_fields(name: &str, positional: &[&str], args: Vec<String>) -> Result<Map<String, Value>, Error> {
+    let (named, rest) = match positional.split_last() {
+        Some((last, named)) if last.ends_with("...") => (named, Some(last.trim_end_matches("..."))),
+        _ => (positional, None),
+    };
+    let required = named.iter().take_while(|field| !field.ends_with('?')).count();
+
+    let mut fields = Map::new();
+    let mut values = Vec::new();
+    let mut options_only = false;
+    for arg in args {
+        if !options_only && arg == "--" && values.len() >= required {
+            options_only = true;
+            continue;
+        }
+
+        let filled = options_only || (rest.is_none() && values.len() >= named.len());
+        let (key, value) = match option(&arg).filter(|_| filled) {
+            Some((key, Some(value))) => (key, Value::String(value.to_string())),
+            Some((key, None)) => (key, Value::Bool(true)),
+            None if options_only => {
+                return Err(invalid_arguments(name, format!("expected an option after '--', got '{}'", arg)));
+            }
+            None => {
+                values.push(arg);
+                continue;
+            }
+        };
+        let key = key.replace('-', "_");
+        if fields.insert(key.clone(), value).is_some() {
+            return Err(Error::invalid_argument(name, &key, "given more than once"));
+        }
+    }
+
+    let mut values = values.into_iter();
+    for (field, value) in named.iter().zip(values.by_ref()) {
+        let field = field.trim_end_matches('?');
+        fields.insert(field.to_string(), Value::String(value));
+    }
+
+    let remaining: Vec<Value> = values.map(Value::String).collect();
+    match rest {
+        Some(field) if !remaining.is_empty() => {
+            fields.insert(field.to_string(), Value::Array(remaining));
+        }
+        None if !remaining.is_empty() => {
+            return Err(invalid_arguments(
+                name,
+                if named.is_empty() {
+                    "takes no positional arguments".to_string()
+                } else {
+                    let named: Vec<&str> = named.iter().map(|field| field.trim_end_matches('?')).collect();
+                    format!("too many arguments, expected {}", named.join(", "))
+                },
+            ));
+        }
+        _ => {}
+    }
+    Ok(fields)
+}
+
+// `--name=value` or `--name`, where the name is lowercase letters and dashes so
+// that content such as `-- comment` stays positional
+fn option(arg: &str) -> Option<(&str, Option<&str>)> {
+    let option = arg.strip_prefix("--")?;
+    let (key, value) = match option.split_once('=') {
+        Some((key, value)) => (key, Some(value)),
+        None => (option, None),
+    };
+    let valid = key.starts_with(|c: char| c.is_ascii_lowercase())
+        && key.chars().all(|c| c.is_ascii_lowercase() || c == '-');
+    valid.then_some((key, value))
+}
+
+pub fn unknown_command(name: &str) -> Error {
+    Error::UnknownCommand {
+        command: name.to_string(),
+        suggestions: help::suggestions(name),
+    }
+}
+
+fn invalid_arguments(name: &str, message: impl Into<String>) -> Error {
+    Error::InvalidArguments {
+        command: name.to_string(),
+        field: None,
+        message: message.into(),
+    }
+}
+
+fn describe_error(name: &str, error: serde_path_to_error::Error<serde_json::Error>) -> Error {
+    let path = error.path().to_string();
+    let field = path
+        .strip_prefix(name)
+        .map(|field| field.trim_start_matches('.'))
+        .filter(|field| !field.is_empty());
+    Error::InvalidArguments {
+        command: name.to_string(),
+        field: field.map(str::to_string),
+        message: error.inner().to_string(),
+    }
+}
+
+// Checks between arguments that their types cannot express
+fn validate(name: &str, command: &Command) -> Result<(), Error> {
+    if let Command::CopyPathWithContent { start_line, end_line, .. } = command {
+        match (start_line, end_line) {
+            (Some(_), None) => return Err(Error::invalid_argument(name, "end_line", "required with 'start_line'")),
+            (None, Some(_)) => return Err(Error::invalid_argument(name, "start_line", "required with 'end_line'")),
+            (Some(start), Some(end)) if end < start => {
+                return Err(Error::invalid_argument(
+                    name,
+                    "end_line",
+                    format!("{} is before start_line {}", end, start),
+                ))
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+// Values parsed from their text, with the error messages of their own parser
+trait ArgumentValue: Sized {
+    fn parse_argument(value: &str) -> Result<Self, String>;
+}
+
+impl ArgumentValue for OutputFormat {
+    fn parse_argument(value: &str) -> Result<Self, String> {
+        OutputFormat::parse(value)
+    }
+}
+
+impl ArgumentValue for TokenEstimator {
+    fn parse_argument(value: &str) -> Result<Self, String> {
+        TokenEstimator::parse(value)
+    }
+}
+
+impl ArgumentValue for BudgetMode {
+    fn parse_argument(value: &str) -> Result<Self, String> {
+        BudgetMode::parse(value)
+    }
+}
+
+impl ArgumentValue for BatchMode {
+    fn parse_argument(value: &str) -> Result<Self, String> {
+        BatchMode::parse(value)
+    }
+}
+
+impl ArgumentValue for ResponseFormat {
+    fn parse_argument(value: &str) -> Result<Self, String> {
+        ResponseFormat::parse(value)
+    }
+}
+
+impl ArgumentValue for DisplayMode {
+    fn parse_argument(value: &str) -> Result<Self, String> {
+        DisplayMode::parse(value)
+    }
+}
+
+impl ArgumentValue for LimitUnit {
+    fn parse_argument(value: &str) -> Result<Self, String> {
+        LimitUnit::parse(value)
+    }
+}
+
+impl ArgumentValue for SortKey {
+    fn parse_argument(value: &str) -> Result<Self, String> {
+        SortKey::parse(value)
+    }
+}
+
+impl ArgumentValue for Vec<TrimStrategy> {
+    fn parse_argument(value: &str) -> Result<Self, String> {
+        TrimStrategy::parse_list(value)
+    }
+}
+
+fn parsed<'de, D: Deserializer<'de>, T: ArgumentValue>(deserializer: D) -> Result<T, D::Error> {
+    let text = String::deserialize(deserializer)?;
+    T::parse_argument(&text).map_err(de::Error::custom)
+}
+
+fn optional_parsed<'de, D: Deserializer<'de>, T: ArgumentValue>(deserializer: D) -> Result<Option<T>, D::Error> {
+    parsed(deserializer).map(Some)
+}
+
+// Positional arguments are always text, so numbers and switches also accept
+// their text form
+fn number<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr + DeserializeOwned,
+{
+    match Value::deserialize(deserializer)? {
+        Value::String(text) => text
+            .trim()
+            .parse()
+            .map_err(|_| de::Error::custom(format!("expected a number, got '{}'", text))),
+        value => T::deserialize(value).map_err(de::Error::custom),
+    }
+}
+
+fn optional_number<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr + DeserializeOwned,
+{
+    number(deserializer).map(Some)
+}
+
+// A number, or `off` (or null) for none
+fn number_or_off<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr + DeserializeOwned,
+{
+    match Value::deserialize(deserializer)? {
+        Value::Null => Ok(None),
+        Value::String(text) if text.trim().eq_ignore_ascii_case("off") => Ok(None),
+        Value::String(text) => text
+            .trim()
+            .parse()
+            .map(Some)
+            .map_err(|_| de::Error::custom(format!("expected a number or 'off', got '{}'", text))),
+        value => T::deserialize(value).map(Some).map_err(de::Error::custom),
+    }
+}
+
+fn switch<'de, D: Deserializer<'de>>(deserializer: D) -> Result<bool, D::Error> {
+    match Value::deserialize(deserializer)? {
+        Value::Bool(enabled) => Ok(enabled),
+        Value::String(text) => parse_bool(&text).map_err(de::Error::custom),
+        other => Err(de::Error::custom(format!("expected on or off, got {}", other))),
+    }
+}
+
+// A comma separated list or an array of globs
+fn glob_list<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<String>, D::Error> {
+    match Value::deserialize(deserializer)? {
+        Value::String(text) => Ok(directory::parse_glob_list(&text)),
+        value => Vec::<String>::deserialize(value).map_err(de::Error::custom),
+    }
+}
+
+// A JSON value, or JSON text as passed positionally
+fn json<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Value, D::Error> {
+    match Value::deserialize(deserializer)? {
+        Value::String(text) => serde_json::from_str(&text).map_err(de::Error::custom),
+        value => Ok(value),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(values: &[&str]) -> Vec<String> {
+        values.iter().map(|value| value.to_string()).collect()
+    }
+
+    #[test]
+    fn test_positional_and_json_forms_agree() {
+        let positional = Command::parse(
+            "copy_path_with_content",
+            args(&["src/a,b.rs", "fn a() {}", "3", "4", "--", "--format=markdown", "--buffer=review"]),
+        )
+        .unwrap();
+        let json = Command::parse(
+            "copy_path_with_content",
+            args(&[r#"{"file_path": "src/a,b.rs", "content": "fn a() {}", "start_line": 3, "end_line": 4,
+                      "format": "markdown", "buffer": "review"}"#]),
+        )
+        .unwrap();
+
+        assert_eq!(positional, json);
+        assert!(matches!(
+            json,
+            Command::CopyPathWithContent { start_line: Some(3), end_line: Some(4), format: Some(OutputFormat::Markdown), .. }
+        ));
+    }
+
+    #[test]
+    fn test_flags_lists_and_commands_without_arguments() {
+        assert_eq!(
+            Command::parse("create_folder", args(&["API", "src/a.rs", "src/b.rs"])).unwrap(),
+            Command::CreateFolder {
+                name: "API".to_string(),
+                files: args(&["src/a.rs", "src/b.rs"]),
+            }
+        );
+        assert_eq!(
+            Command::parse("delete_folder", args(&["api", "--recursive"])).unwrap(),
+            Command::DeleteFolder {
+                folder: "api".to_string(),
+                recursive: true,
+            }
+        );
+        assert_eq!(Command::parse("status", vec![]).unwrap(), Command::Status);
+        assert_eq!(Command::parse("status", args(&["{}"])).unwrap(), Command::Status);
+        assert_eq!(
+            Command::parse("create_folder", args(&["{x}"])).unwrap(),
+            Command::CreateFolder {
+                name: "{x}".to_string(),
+                files: vec![],
+            }
+        );
+        assert_eq!(
+            Command::parse("switch_buffer", args(&["{tmp}"])).unwrap(),
+            Command::SwitchBuffer { name: "{tmp}".to_string() }
+        );
+        assert_eq!(
+            Command::parse("help", args(&["{…}"])).unwrap(),
+            Command::Help { command: Some("{…}".to_string()) }
+        );
+        assert!(!Command::Status.is_mutating());
+
+        // Content that merely starts with dashes stays positional
+        let sql = Command::parse("copy_path_with_content", args(&["q.sql", "-- all users"])).unwrap();
+        assert!(matches!(sql, Command::CopyPathWithContent { content, .. } if content == "-- all users"));
+    }
+
+    #[test]
+    fn test_positional_text_shaped_like_options() {
+        let copy = Command::parse("copy_path_with_content", args(&["a.rs", "--no-cache"])).unwrap();
+        assert!(matches!(copy, Command::CopyPathWithContent { ref content, format: None, .. } if content == "--no-cache"));
+
+        let copy = Command::parse("copy_path_with_content", args(&["a.rs", "--color=auto", "1", "1", "--verbose"])).unwrap();
+        assert!(matches!(
+            copy,
+            Command::CopyPathWithContent { ref content, selected_content: Some(ref selected), .. }
+                if content == "--color=auto" && selected == "--verbose"
+        ));
+
+        assert_eq!(
+            Command::parse("switch_buffer", args(&["--x"])).unwrap(),
+            Command::SwitchBuffer { name: "--x".to_string() }
+        );
+        assert_eq!(
+            Command::parse("create_folder", args(&["API", "--draft.md"])).unwrap(),
+            Command::CreateFolder {
+                name: "API".to_string(),
+                files: args(&["--draft.md"]),
+            }
+        );
+
+        // Options follow the arguments, or a `--` once the required ones are given
+        let copy = Command::parse("copy_path_with_content", args(&["a.rs", "x", "--", "--format=xml"])).unwrap();
+        assert!(matches!(copy, Command::CopyPathWithContent { format: Some(OutputFormat::Xml), start_line: None, .. }));
+        let copy = Command::parse("copy_path_with_content", args(&["a.rs", "--", "--", "--format=xml"])).unwrap();
+        assert!(matches!(copy, Command::CopyPathWithContent { ref content, format: Some(OutputFormat::Xml), .. } if content == "--"));
+
+        let error = Command::parse("fit_to_budget", args(&["--", "4000"])).unwrap_err();
+        assert_eq!(error.to_string(), "Invalid arguments for fit_to_budget: expected an option after '--', got '4000'");
+    }
+
+    #[test]
+    fn test_typed_arguments_accept_json_values() {
+        let positional = Command::parse("set_token_budget", args(&["8000", "refuse"])).unwrap();
+        let json = Command::parse("set_token_budget", args(&[r#"{"limit": 8000, "mode": "refuse"}"#])).unwrap();
+        assert_eq!(positional, json);
+        assert_eq!(
+            json,
+            Command::SetTokenBudget {
+                limit: Some(8000),
+                mode: Some(BudgetMode::Refuse),
+            }
+        );
+        assert_eq!(
+            Command::parse("set_token_budget", args(&["off"])).unwrap(),
+            Command::SetTokenBudget { limit: None, mode: None }
+        );
+        let error = Command::parse("set_token_budget", args(&["lots"])).unwrap_err();
+        assert_eq!(error.to_string(), "Invalid argument 'limit' for set_token_budget: expected a number or 'off', got 'lots'");
+
+        assert_eq!(
+            Command::parse("sort_clipboard", args(&[r#"{"key": "time"}"#])).unwrap(),
+            Command::SortClipboard { key: SortKey::Time }
+        );
+        assert!(Command::parse("sort_clipboard", args(&["size"])).is_err());
+
+        let fit = Command::parse("fit_to_budget", args(&[r#"{"limit": 20000, "unit": "bytes"}"#])).unwrap();
+        assert!(matches!(fit, Command::FitToBudget { limit: Some(20000), unit: Some(LimitUnit::Bytes), .. }));
+        let error = Command::parse("fit_to_budget", args(&["100", "--unit=lines"])).unwrap_err();
+        assert!(matches!(error, Error::InvalidArguments { field: Some(ref field), .. } if field == "unit"));
+    }
+
+    #[test]
+    fn test_every_signature_names_a_command() {
+        for (name, positional) in SIGNATURES {
+            // Without arguments a command either parses or reports what is missing
+            let error = match Command::parse(name, vec![]) {
+                Ok(_) => continue,
+                Err(error) => error,
+            };
+            assert_eq!(error.code(), "invalid_arguments", "{}", name);
+            assert!(error.to_string().contains("missing field"), "{}: {} ({:?})", name, error, positional);
+        }
+    }
+
+    #[test]
+    fn test_errors_name_the_field() {
+        let error = Command::parse("copy_path_with_content", args(&["a.rs", "x", "3"])).unwrap_err();
+        assert_eq!(error.to_string(), "Invalid argument 'end_line' for copy_path_with_content: required with 'start_line'");
+
+        let error = Command::parse("copy_path_with_content", args(&["a.rs", "x", "three", "4"])).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Invalid argument 'start_line' for copy_path_with_content: expected a number, got 'three'"
+        );
+
+        let error = Command::parse("copy_path_with_content", args(&[r#"{"file_path": "a.rs"}"#])).unwrap_err();
+        assert_eq!(error.to_string(), "Invalid arguments for copy_path_with_content: missing field `content`");
+
+        let error = Command::parse("set_output_format", args(&[r#"{"format": "html"}"#])).unwrap_err();
+        assert!(matches!(error, Error::InvalidArguments { field: Some(ref field), .. } if field == "format"));
+        assert!(error.to_string().contains("Unknown output format"), "{}", error);
+
+        let error = Command::parse("delete_folder", args(&[r#"{"folder": "api", "recurse": true}"#])).unwrap_err();
+        assert!(error.to_string().contains("unknown field `recurse`"), "{}", error);
+
+        let error = Command::parse("status", args(&["extra"])).unwrap_err();
+        assert_eq!(error.to_string(), "Invalid arguments for status: takes no positional arguments");
+        let error = Command::parse("status", args(&[r#"{"verbose": true}"#])).unwrap_err();
+        assert_eq!(error.to_string(), "Invalid arguments for status: takes no arguments");
+        assert_eq!(
+            Command::parse("stauts", vec![]).unwrap_err(),
+            Error::UnknownCommand {
+                command: "stauts".to_string(),
+                suggestions: vec!["status".to_string()],
+            }
+        );
+    }
+}
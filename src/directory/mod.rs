@@ -0,0 +1,279 @@
+use crate::error::Error;
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use ignore::WalkBuilder;
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+
+// Per-project ignore rules, in .gitignore syntax, for files that belong in the
+// repository but not in a copied directory
+pub const PROJECT_IGNORE_FILE: &str = ".copypathignore";
+
+pub const DEFAULT_MAX_FILE_SIZE: u64 = 256 * 1024;
+
+// How much of a file is inspected for NUL bytes
+const BINARY_SNIFF_LEN: usize = 8 * 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SkipReason {
+    Binary,
+    TooLarge(u64),
+    Unreadable(String),
+}
+
+impl SkipReason {
+    pub fn name(&self) -> &'static str {
+        match self {
+            SkipReason::Binary => "binary",
+            SkipReason::TooLarge(_) => "too_large",
+            SkipReason::Unreadable(_) => "unreadable",
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        match self {
+            SkipReason::Binary => "binary".to_string(),
+            SkipReason::TooLarge(size) => format!("{} bytes, too large", size),
+            SkipReason::Unreadable(error) => format!("unreadable: {}", error),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DirectoryOptions {
+    // Globs matched against paths relative to the directory; empty includes everything
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+    pub max_file_size: u64,
+}
+
+impl Default for DirectoryOptions {
+    fn default() -> Self {
+        Self {
+            include: Vec::new(),
+            exclude: Vec::new(),
+            max_file_size: DEFAULT_MAX_FILE_SIZE,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct DirectoryContents {
+    // (path, content), sorted by path
+    pub files: Vec<(String, String)>,
+    pub skipped: Vec<(String, SkipReason)>,
+}
+
+// Walks `root` recursively, honoring .gitignore (with or without a git
+// repository), .ignore and PROJECT_IGNORE_FILE files, and skipping hidden
+// entries. Paths are `root` joined with the relative path, so they read the
+// way the directory was given.
+pub fn collect_directory(root: &str, options: &DirectoryOptions) -> Result<DirectoryContents, Error> {
+    let mut contents = DirectoryContents::default();
+    for path in find_files(root, &options.include, &options.exclude)? {
+        match read_text_file(Path::new(&path), options.max_file_size) {
+            Ok(content) => contents.files.push((path, content)),
+            Err(reason) => contents.skipped.push((path, reason)),
+        }
+    }
+    Ok(contents)
+}
+
+// Paths of the files under `root` matching any `include` glob (all files when
+// empty) and no `exclude` glob, walked as in `collect_directory` and sorted
+pub fn find_files(root: &str, include: &[String], exclude: &[String]) -> Result<Vec<String>, Error> {
+    let root_path = Path::new(root);
+    if !root_path.is_dir() {
+        return Err(Error::NotADirectory { path: root.to_string() });
+    }
+
+    let include_set = build_glob_set(include)?;
+    let exclude_set = build_glob_set(exclude)?;
+
+    let walker = WalkBuilder::new(root_path)
+        .require_git(false)
+        .add_custom_ignore_filename(PROJECT_IGNORE_FILE)
+        .sort_by_file_name(|a, b| a.cmp(b))
+        .build();
+
+    let mut paths = Vec::new();
+    for entry in walker {
+        let entry = entry.map_err(|e| {
+            let kind = e.io_error().map_or(std::io::ErrorKind::Other, std::io::Error::kind);
+            Error::Io {
+                path: root.to_string(),
+                kind,
+                message: e.to_string(),
+            }
+        })?;
+        if !entry.file_type().is_some_and(|file_type| file_type.is_file()) {
+            continue;
+        }
+
+        let relative = entry.path().strip_prefix(root_path).unwrap_or(entry.path());
+        if !include.is_empty() && !include_set.is_match(relative) {
+            continue;
+        }
+        if exclude_set.is_match(relative) {
+            continue;
+        }
+
+        // Files under the current directory read best without a `./` prefix
+        let path = if root == "." { relative } else { entry.path() };
+        paths.push(path.to_string_lossy().to_string());
+    }
+
+    // Walk order is per directory; sort the full paths so the result does not
+    // depend on how directories and files interleave
+    paths.sort();
+    Ok(paths)
+}
+
+pub fn parse_glob_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(|glob| glob.trim().to_string())
+        .filter(|glob| !glob.is_empty())
+        .collect()
+}
+
+pub fn validate_glob(glob: &str) -> Result<(), Error> {
+    parse_glob(glob).map(|_| ())
+}
+
+fn parse_glob(glob: &str) -> Result<Glob, Error> {
+    Glob::new(glob).map_err(|e| Error::InvalidGlob {
+        glob: glob.to_string(),
+        reason: e.kind().to_string(),
+    })
+}
+
+fn build_glob_set(globs: &[String]) -> Result<GlobSet, Error> {
+    let mut builder = GlobSetBuilder::new();
+    for glob in globs {
+        builder.add(parse_glob(glob)?);
+    }
+    builder.build().map_err(|e| Error::InvalidGlob {
+        glob: globs.join(","),
+        reason: e.kind().to_string(),
+    })
+}
+
+fn read_text_file(path: &Path, max_file_size: u64) -> Result<String, SkipReason> {
+    let size = fs::metadata(path)
+        .map_err(|e| SkipReason::Unreadable(e.to_string()))?
+        .len();
+    if size > max_file_size {
+        return Err(SkipReason::TooLarge(size));
+    }
+
+    let mut bytes = Vec::with_capacity(size as usize);
+    fs::File::open(path)
+        .and_then(|mut file| file.read_to_end(&mut bytes))
+        .map_err(|e| SkipReason::Unreadable(e.to_string()))?;
+
+    if bytes[..bytes.len().min(BINARY_SNIFF_LEN)].contains(&0) {
+        return Err(SkipReason::Binary);
+    }
+    String::from_utf8(bytes).map_err(|_| SkipReason::Binary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn fixture_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("copy-path-directory-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn write(dir: &Path, relative: &str, content: &[u8]) {
+        let path = dir.join(relative);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    fn relative_paths(dir: &Path, contents: &DirectoryContents) -> Vec<String> {
+        contents
+            .files
+            .iter()
+            .map(|(path, _)| Path::new(path).strip_prefix(dir).unwrap().to_string_lossy().replace('\\', "/"))
+            .collect()
+    }
+
+    #[test]
+    fn test_honors_ignore_files_in_sorted_order() {
+        let dir = fixture_dir("ignore");
+        write(&dir, "src/main.rs", b"fn main() {}");
+        write(&dir, "src/lib.rs", b"pub fn lib() {}");
+        write(&dir, "build/out.rs", b"generated");
+        write(&dir, "notes.md", b"notes");
+        write(&dir, "secret.env", b"KEY=1");
+        write(&dir, "fixtures/big.json", b"{}");
+        write(&dir, ".hidden", b"hidden");
+        write(&dir, ".gitignore", b"build/\n");
+        write(&dir, ".ignore", b"*.env\n");
+        write(&dir, PROJECT_IGNORE_FILE, b"fixtures/\n");
+
+        let contents = collect_directory(&dir.to_string_lossy(), &DirectoryOptions::default()).unwrap();
+        assert_eq!(relative_paths(&dir, &contents), vec!["notes.md", "src/lib.rs", "src/main.rs"]);
+        assert!(contents.skipped.is_empty());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_include_and_exclude_globs() {
+        let dir = fixture_dir("globs");
+        write(&dir, "src/main.rs", b"fn main() {}");
+        write(&dir, "src/main_test.rs", b"#[test] fn t() {}");
+        write(&dir, "README.md", b"readme");
+
+        let options = DirectoryOptions {
+            include: parse_glob_list("**/*.rs"),
+            exclude: parse_glob_list("*_test.rs, "),
+            ..DirectoryOptions::default()
+        };
+        let contents = collect_directory(&dir.to_string_lossy(), &options).unwrap();
+        assert_eq!(relative_paths(&dir, &contents), vec!["src/main.rs"]);
+
+        let invalid = DirectoryOptions {
+            include: vec!["src/[".to_string()],
+            ..DirectoryOptions::default()
+        };
+        assert!(matches!(
+            collect_directory(&dir.to_string_lossy(), &invalid),
+            Err(Error::InvalidGlob { glob, .. }) if glob == "src/["
+        ));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_skips_binary_and_oversized_files() {
+        let dir = fixture_dir("skip");
+        write(&dir, "image.png", &[0x89, b'P', b'N', b'G', 0, 0]);
+        write(&dir, "latin1.txt", &[b'c', b'a', b'f', 0xe9]);
+        write(&dir, "large.txt", &[b'a'; 100]);
+        write(&dir, "small.txt", b"ok");
+
+        let options = DirectoryOptions {
+            max_file_size: 50,
+            ..DirectoryOptions::default()
+        };
+        let contents = collect_directory(&dir.to_string_lossy(), &options).unwrap();
+        assert_eq!(relative_paths(&dir, &contents), vec!["small.txt"]);
+
+        let reasons: Vec<_> = contents.skipped.iter().map(|(_, reason)| reason.clone()).collect();
+        assert_eq!(reasons, vec![SkipReason::Binary, SkipReason::TooLarge(100), SkipReason::Binary]);
+
+        assert!(matches!(
+            collect_directory(&dir.join("small.txt").to_string_lossy(), &options),
+            Err(Error::NotADirectory { .. })
+        ));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,348 @@
+use std::fmt;
+use std::io;
+
+// Everything a command can fail with. Managers return these with the ids,
+// paths and values involved; the message is only produced by `Display`, at the
+// command boundary, and `code` gives callers something stable to match on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    // `suggestions` are the closest command names, best first
+    UnknownCommand {
+        command: String,
+        suggestions: Vec<String>,
+    },
+    // `field` is None when the arguments as a whole are wrong
+    InvalidArguments {
+        command: String,
+        field: Option<String>,
+        message: String,
+    },
+
+    FolderNotFound {
+        folder: String,
+    },
+    ParentFolderNotFound {
+        folder: String,
+    },
+    // (id, name) of every folder the key could mean
+    AmbiguousFolder {
+        key: String,
+        matches: Vec<(String, String)>,
+    },
+    FolderExists {
+        name: String,
+    },
+    InvalidFolderName {
+        name: String,
+        reason: String,
+    },
+    FolderHasSubfolders {
+        folder: String,
+        name: String,
+        subfolders: usize,
+    },
+    FolderCycle {
+        folder: String,
+        parent: String,
+    },
+    FileAlreadyInFolder {
+        folder: String,
+        path: String,
+    },
+    FileNotInFolder {
+        folder: String,
+        path: String,
+    },
+    PatternAlreadyInFolder {
+        folder: String,
+        pattern: String,
+    },
+    PatternNotInFolder {
+        folder: String,
+        pattern: String,
+    },
+    // `name` is the folder definition at fault, if the JSON itself was readable
+    InvalidFolderImport {
+        name: Option<String>,
+        reason: String,
+    },
+    NoReadableFiles {
+        folder: Option<String>,
+    },
+
+    InvalidBufferName {
+        name: String,
+        reason: &'static str,
+    },
+    BufferNotFound {
+        buffer: String,
+    },
+    MergeIntoItself {
+        buffer: String,
+    },
+    EntryNotInClipboard {
+        path: String,
+    },
+    PositionOutOfRange {
+        position: usize,
+        len: usize,
+    },
+    EntryAlreadyFirst {
+        path: String,
+    },
+    EntryAlreadyLast {
+        path: String,
+    },
+    NothingToUndo,
+    NothingToRedo,
+    HistoryEntryNotFound {
+        index: usize,
+    },
+    BudgetExceeded {
+        tokens: usize,
+        limit: usize,
+        reason: String,
+    },
+    NoFilesToCopy {
+        path: String,
+    },
+
+    NotADirectory {
+        path: String,
+    },
+    InvalidGlob {
+        glob: String,
+        reason: String,
+    },
+    Io {
+        path: String,
+        kind: io::ErrorKind,
+        message: String,
+    },
+    // The state file was written by a newer version and must not be overwritten
+    StateFileTooNew {
+        path: String,
+        version: u32,
+        supported: u32,
+    },
+
+    TemplateNotFound {
+        name: String,
+    },
+    InvalidTemplate {
+        name: String,
+        reason: String,
+    },
+}
+
+impl Error {
+    pub fn io(path: impl fmt::Display, error: &io::Error) -> Self {
+        Error::Io {
+            path: path.to_string(),
+            kind: error.kind(),
+            message: error.to_string(),
+        }
+    }
+
+    pub fn invalid_argument(command: &str, field: &str, message: impl Into<String>) -> Self {
+        Error::InvalidArguments {
+            command: command.to_string(),
+            field: Some(field.to_string()),
+            message: message.into(),
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            Error::UnknownCommand { .. } => "unknown_command",
+            Error::InvalidArguments { .. } => "invalid_arguments",
+            Error::FolderNotFound { .. } => "folder_not_found",
+            Error::ParentFolderNotFound { .. } => "parent_folder_not_found",
+            Error::AmbiguousFolder { .. } => "ambiguous_folder",
+            Error::FolderExists { .. } => "folder_exists",
+            Error::InvalidFolderName { .. } => "invalid_folder_name",
+            Error::FolderHasSubfolders { .. } => "folder_has_subfolders",
+            Error::FolderCycle { .. } => "folder_cycle",
+            Error::FileAlreadyInFolder { .. } => "file_already_in_folder",
+            Error::FileNotInFolder { .. } => "file_not_in_folder",
+            Error::PatternAlreadyInFolder { .. } => "pattern_already_in_folder",
+            Error::PatternNotInFolder { .. } => "pattern_not_in_folder",
+            Error::InvalidFolderImport { .. } => "invalid_folder_import",
+            Error::NoReadableFiles { .. } => "no_readable_files",
+            Error::InvalidBufferName { .. } => "invalid_buffer_name",
+            Error::BufferNotFound { .. } => "buffer_not_found",
+            Error::MergeIntoItself { .. } => "merge_into_itself",
+            Error::EntryNotInClipboard { .. } => "entry_not_in_clipboard",
+            Error::PositionOutOfRange { .. } => "position_out_of_range",
+            Error::EntryAlreadyFirst { .. } => "entry_already_first",
+            Error::EntryAlreadyLast { .. } => "entry_already_last",
+            Error::NothingToUndo => "nothing_to_undo",
+            Error::NothingToRedo => "nothing_to_redo",
+            Error::HistoryEntryNotFound { .. } => "history_entry_not_found",
+            Error::BudgetExceeded { .. } => "budget_exceeded",
+            Error::NoFilesToCopy { .. } => "no_files_to_copy",
+            Error::NotADirectory { .. } => "not_a_directory",
+            Error::InvalidGlob { .. } => "invalid_glob",
+            Error::Io { .. } => "io",
+            Error::StateFileTooNew { .. } => "state_file_too_new",
+            Error::TemplateNotFound { .. } => "template_not_found",
+            Error::InvalidTemplate { .. } => "invalid_template",
+        }
+    }
+
+    // The ids, paths and values involved, as (name, value) pairs with unique names
+    pub fn context(&self) -> Vec<(&'static str, String)> {
+        match self {
+            Error::UnknownCommand { command, suggestions } => {
+                let mut context = vec![("command", command.clone())];
+                if !suggestions.is_empty() {
+                    context.push(("suggestions", suggestions.join(",")));
+                }
+                context
+            }
+            Error::InvalidArguments { command, field, .. } => {
+                let mut context = vec![("command", command.clone())];
+                context.extend(field.iter().map(|field| ("field", field.clone())));
+                context
+            }
+            Error::FolderNotFound { folder }
+            | Error::ParentFolderNotFound { folder }
+            | Error::FolderHasSubfolders { folder, .. } => vec![("folder_id", folder.clone())],
+            Error::AmbiguousFolder { key, matches } => {
+                let ids: Vec<&str> = matches.iter().map(|(id, _)| id.as_str()).collect();
+                vec![("folder", key.clone()), ("candidate_ids", ids.join(","))]
+            }
+            Error::FolderExists { name } | Error::InvalidFolderName { name, .. } => vec![("name", name.clone())],
+            Error::FolderCycle { folder, parent } => {
+                vec![("folder_id", folder.clone()), ("parent_id", parent.clone())]
+            }
+            Error::FileAlreadyInFolder { folder, path } | Error::FileNotInFolder { folder, path } => {
+                vec![("folder_id", folder.clone()), ("path", path.clone())]
+            }
+            Error::PatternAlreadyInFolder { folder, pattern } | Error::PatternNotInFolder { folder, pattern } => {
+                vec![("folder_id", folder.clone()), ("pattern", pattern.clone())]
+            }
+            Error::InvalidFolderImport { name, .. } => name.iter().map(|name| ("name", name.clone())).collect(),
+            Error::NoReadableFiles { folder } => folder.iter().map(|folder| ("folder_id", folder.clone())).collect(),
+            Error::InvalidBufferName { name, .. } => vec![("buffer", name.clone())],
+            Error::BufferNotFound { buffer } | Error::MergeIntoItself { buffer } => vec![("buffer", buffer.clone())],
+            Error::EntryNotInClipboard { path }
+            | Error::EntryAlreadyFirst { path }
+            | Error::EntryAlreadyLast { path }
+            | Error::NoFilesToCopy { path }
+            | Error::NotADirectory { path } => vec![("path", path.clone())],
+            Error::PositionOutOfRange { position, len } => {
+                vec![("position", position.to_string()), ("len", len.to_string())]
+            }
+            Error::NothingToUndo | Error::NothingToRedo => Vec::new(),
+            Error::HistoryEntryNotFound { index } => vec![("index", index.to_string())],
+            Error::BudgetExceeded { tokens, limit, .. } => {
+                vec![("tokens", tokens.to_string()), ("limit", limit.to_string())]
+            }
+            Error::InvalidGlob { glob, .. } => vec![("glob", glob.clone())],
+            Error::Io { path, kind, .. } => vec![("path", path.clone()), ("io_kind", format!("{:?}", kind))],
+            Error::StateFileTooNew { path, version, supported } => vec![
+                ("path", path.clone()),
+                ("version", version.to_string()),
+                ("supported_version", supported.to_string()),
+            ],
+            Error::TemplateNotFound { name } | Error::InvalidTemplate { name, .. } => vec![("template", name.clone())],
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::UnknownCommand { command, suggestions } => match suggestions.as_slice() {
+                [] => write!(f, "Unknown command: {} (run 'help' to list the commands)", command),
+                [suggestion] => write!(f, "Unknown command: {}. Did you mean '{}'?", command, suggestion),
+                [rest @ .., last] => {
+                    let rest: Vec<String> = rest.iter().map(|name| format!("'{}'", name)).collect();
+                    write!(f, "Unknown command: {}. Did you mean {} or '{}'?", command, rest.join(", "), last)
+                }
+            },
+            Error::InvalidArguments { command, field: Some(field), message } => {
+                write!(f, "Invalid argument '{}' for {}: {}", field, command, message)
+            }
+            Error::InvalidArguments { command, field: None, message } => {
+                write!(f, "Invalid arguments for {}: {}", command, message)
+            }
+            Error::FolderNotFound { folder } => write!(f, "Folder '{}' not found", folder),
+            Error::ParentFolderNotFound { folder } => write!(f, "Parent folder '{}' not found", folder),
+            Error::AmbiguousFolder { key, matches } => {
+                let matches: Vec<String> = matches.iter().map(|(id, name)| format!("{} ({})", name, id)).collect();
+                write!(f, "Folder '{}' is ambiguous: {}", key, matches.join(", "))
+            }
+            Error::FolderExists { name } => write!(f, "Folder with the name '{}' already exists", name),
+            Error::InvalidFolderName { reason, .. } => write!(f, "{}", reason),
+            Error::FolderHasSubfolders { name, subfolders, .. } => write!(
+                f,
+                "Folder '{}' has {} subfolders; move them or delete recursively",
+                name, subfolders
+            ),
+            Error::FolderCycle { .. } => write!(f, "Cannot move a folder into itself or one of its subfolders"),
+            Error::FileAlreadyInFolder { path, .. } => write!(f, "File '{}' already exists in folder", path),
+            Error::FileNotInFolder { path, .. } => write!(f, "File '{}' not found in folder", path),
+            Error::PatternAlreadyInFolder { pattern, .. } => write!(f, "Pattern '{}' already exists in folder", pattern),
+            Error::PatternNotInFolder { pattern, .. } => write!(f, "Pattern '{}' not found in folder", pattern),
+            Error::InvalidFolderImport { name: Some(name), reason } => write!(f, "Cannot import '{}': {}", name, reason),
+            Error::InvalidFolderImport { name: None, reason } => write!(f, "Invalid folder import: {}", reason),
+            Error::NoReadableFiles { folder: Some(_) } => write!(f, "No readable files found in folder"),
+            Error::NoReadableFiles { folder: None } => write!(f, "No files could be read successfully"),
+            Error::InvalidBufferName { reason, .. } => write!(f, "Buffer name {}", reason),
+            Error::BufferNotFound { buffer } => write!(f, "Buffer '{}' not found", buffer),
+            Error::MergeIntoItself { .. } => write!(f, "Cannot merge a buffer into itself"),
+            Error::EntryNotInClipboard { path } => write!(f, "File '{}' is not in the clipboard", path),
+            Error::PositionOutOfRange { position, len } => {
+                write!(f, "Position {} is out of range (1-{})", position, len)
+            }
+            Error::EntryAlreadyFirst { path } => write!(f, "'{}' is already first", path),
+            Error::EntryAlreadyLast { path } => write!(f, "'{}' is already last", path),
+            Error::NothingToUndo => write!(f, "Nothing to undo"),
+            Error::NothingToRedo => write!(f, "Nothing to redo"),
+            Error::HistoryEntryNotFound { index } => write!(f, "No history entry {}", index),
+            Error::BudgetExceeded { reason, .. } => write!(f, "Copy refused: {}", reason),
+            Error::NoFilesToCopy { path } => write!(f, "No files to copy in '{}'", path),
+            Error::NotADirectory { path } => write!(f, "'{}' is not a directory", path),
+            Error::InvalidGlob { glob, reason } => write!(f, "Invalid glob '{}': {}", glob, reason),
+            Error::Io { path, message, .. } => write!(f, "{}: {}", path, message),
+            Error::StateFileTooNew { path, version, supported } => write!(
+                f,
+                "{} has state version {}, newer than supported version {}; it is left untouched and changes are not saved",
+                path, version, supported
+            ),
+            Error::TemplateNotFound { name } => write!(f, "Template '{}' not found", name),
+            Error::InvalidTemplate { reason, .. } => write!(f, "{}", reason),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_code_and_context_identify_the_failure() {
+        let error = Error::FileAlreadyInFolder {
+            folder: "folder_2".to_string(),
+            path: "src/lib.rs".to_string(),
+        };
+        assert_eq!(error.code(), "file_already_in_folder");
+        assert_eq!(
+            error.context(),
+            vec![("folder_id", "folder_2".to_string()), ("path", "src/lib.rs".to_string())]
+        );
+        assert_eq!(error.to_string(), "File 'src/lib.rs' already exists in folder");
+    }
+
+    #[test]
+    fn test_io_errors_keep_their_kind() {
+        let error = Error::io("state.json", &io::Error::from(io::ErrorKind::PermissionDenied));
+        assert_eq!(error.code(), "io");
+        assert!(matches!(error, Error::Io { kind: io::ErrorKind::PermissionDenied, .. }));
+        assert!(error.context().contains(&("io_kind", "PermissionDenied".to_string())));
+    }
+}
@@ -1,86 +1,267 @@
+use crate::directory;
+use crate::error::Error;
 use crate::models::{Folder, CopiedFile};
+use crate::output::OutputFormatter;
+use crate::paths::Workspace;
+use serde::{Deserialize, Serialize};
 use std::fs;
+use unicode_normalization::UnicodeNormalization;
+
+pub const MAX_FOLDER_NAME_LEN: usize = 100;
+
+// A folder definition accepted by `import_from_json`
+#[derive(Debug, Deserialize)]
+struct FolderImport {
+    name: String,
+    #[serde(default)]
+    files: Vec<String>,
+    #[serde(default)]
+    patterns: Vec<String>,
+    #[serde(default)]
+    color: Option<String>,
+}
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FolderManager {
     pub folders: Vec<Folder>,
+    // Last number handed out by `generate_folder_id`
+    #[serde(default)]
+    pub next_folder_id: u64,
 }
 
 impl FolderManager {
     pub fn new() -> Self {
         Self {
             folders: Vec::new(),
+            next_folder_id: 0,
         }
     }
 
-    pub fn create_folder(&mut self, name: String, initial_files: Vec<String>) -> Result<String, String> {
-        if name.trim().is_empty() {
-            return Err("Folder name cannot be empty".to_string());
-        }
+    // Returns the id of the new folder
+    pub fn create_folder(&mut self, name: String, initial_files: Vec<String>) -> Result<String, Error> {
+        self.create_folder_in(None, name, initial_files)
+    }
 
-        if self.folders.iter().any(|f| f.name == name) {
-            return Err("Folder with this name already exists".to_string());
+    pub fn create_subfolder(&mut self, parent_id: &str, name: String, initial_files: Vec<String>) -> Result<String, Error> {
+        if self.get_folder(parent_id).is_none() {
+            return Err(Error::ParentFolderNotFound { folder: parent_id.to_string() });
         }
+        self.create_folder_in(Some(parent_id.to_string()), name, initial_files)
+    }
+
+    fn create_folder_in(&mut self, parent_id: Option<String>, name: String, initial_files: Vec<String>) -> Result<String, Error> {
+        let name = self.validate_folder_name(&name, None)?;
 
         let folder_id = self.generate_folder_id();
-        let mut folder = Folder::new(folder_id, name.clone());
+        let mut folder = Folder::new(folder_id.clone(), name);
+        folder.parent_id = parent_id;
 
         for file_path in initial_files {
             folder.add_file(file_path);
         }
 
         self.folders.push(folder);
-        Ok(format!("Folder '{}' created successfully", name))
+        Ok(folder_id)
     }
 
-    pub fn delete_folder(&mut self, folder_id: &str) -> Result<String, String> {
-        if let Some(index) = self.folders.iter().position(|f| f.id == folder_id) {
-            let folder_name = self.folders[index].name.clone();
-            self.folders.remove(index);
-            Ok(format!("Folder '{}' deleted successfully", folder_name))
-        } else {
-            Err("Folder not found".to_string())
+    // Refuses folders that still have subfolders; see `delete_folder_recursive`.
+    // Returns the deleted folder.
+    pub fn delete_folder(&mut self, folder_id: &str) -> Result<Folder, Error> {
+        let index = self.folder_index(folder_id)?;
+        let subfolders = self.children(folder_id).len();
+        if subfolders > 0 {
+            return Err(Error::FolderHasSubfolders {
+                folder: folder_id.to_string(),
+                name: self.folders[index].name.clone(),
+                subfolders,
+            });
         }
+
+        Ok(self.folders.remove(index))
+    }
+
+    // Returns the deleted folders, `folder_id` first
+    pub fn delete_folder_recursive(&mut self, folder_id: &str) -> Result<Vec<Folder>, Error> {
+        self.folder_index(folder_id)?;
+        let mut doomed = vec![folder_id.to_string()];
+        doomed.extend(self.descendants(folder_id));
+
+        let (mut deleted, kept): (Vec<Folder>, Vec<Folder>) =
+            self.folders.drain(..).partition(|f| doomed.contains(&f.id));
+        self.folders = kept;
+        deleted.sort_by_key(|f| doomed.iter().position(|id| *id == f.id));
+        Ok(deleted)
+    }
+
+    // `new_parent_id` of None moves the folder to the top level
+    pub fn move_folder(&mut self, folder_id: &str, new_parent_id: Option<&str>) -> Result<(), Error> {
+        let index = self.folder_index(folder_id)?;
+
+        if let Some(parent_id) = new_parent_id {
+            if self.get_folder(parent_id).is_none() {
+                return Err(Error::ParentFolderNotFound { folder: parent_id.to_string() });
+            }
+            if parent_id == folder_id || self.descendants(folder_id).iter().any(|id| id == parent_id) {
+                return Err(Error::FolderCycle {
+                    folder: folder_id.to_string(),
+                    parent: parent_id.to_string(),
+                });
+            }
+        }
+
+        self.folders[index].parent_id = new_parent_id.map(str::to_string);
+        Ok(())
+    }
+
+    pub fn children(&self, folder_id: &str) -> Vec<&Folder> {
+        self.folders
+            .iter()
+            .filter(|f| f.parent_id.as_deref() == Some(folder_id))
+            .collect()
+    }
+
+    // Ids of every folder below `folder_id`, each followed by its own subfolders
+    pub fn descendants(&self, folder_id: &str) -> Vec<String> {
+        let mut found = Vec::new();
+        self.collect_descendants(folder_id, folder_id, &mut found);
+        found
     }
 
-    pub fn rename_folder(&mut self, folder_id: &str, new_name: String) -> Result<String, String> {
-        if new_name.trim().is_empty() {
-            return Err("Folder name cannot be empty".to_string());
+    fn collect_descendants(&self, folder_id: &str, origin: &str, found: &mut Vec<String>) {
+        for child in self.children(folder_id) {
+            // Guards against cycles in hand-edited state files
+            if child.id != origin && !found.contains(&child.id) {
+                found.push(child.id.clone());
+                self.collect_descendants(&child.id, origin, found);
+            }
         }
+    }
 
-        if self.folders.iter().any(|f| f.name == new_name && f.id != folder_id) {
-            return Err("Folder with this name already exists".to_string());
+    // (depth, folder) in display order. Folders whose parent no longer exists
+    // are shown at the top level.
+    pub fn folder_tree(&self) -> Vec<(usize, &Folder)> {
+        let is_root = |folder: &Folder| {
+            folder
+                .parent_id
+                .as_deref()
+                .is_none_or(|parent| self.get_folder(parent).is_none())
+        };
+
+        let mut tree = Vec::new();
+        for root in self.folders.iter().filter(|f| is_root(f)) {
+            self.push_subtree(root, 0, &mut tree);
         }
+        tree
+    }
 
-        if let Some(folder) = self.folders.iter_mut().find(|f| f.id == folder_id) {
-            let old_name = folder.name.clone();
-            folder.name = new_name.clone();
-            Ok(format!("Folder renamed from '{}' to '{}'", old_name, new_name))
-        } else {
-            Err("Folder not found".to_string())
+    fn push_subtree<'a>(&'a self, folder: &'a Folder, depth: usize, tree: &mut Vec<(usize, &'a Folder)>) {
+        if tree.iter().any(|(_, f)| f.id == folder.id) {
+            return;
+        }
+        tree.push((depth, folder));
+        for child in self.children(&folder.id) {
+            self.push_subtree(child, depth + 1, tree);
         }
     }
 
-    pub fn add_file_to_folder(&mut self, folder_id: &str, file_path: String) -> Result<String, String> {
-        if let Some(folder) = self.folders.iter_mut().find(|f| f.id == folder_id) {
-            if folder.add_file(file_path.clone()) {
-                Ok(format!("File '{}' added to folder '{}'", file_path, folder.name))
-            } else {
-                Err("File already exists in folder".to_string())
+    // Returns the name the folder had before
+    pub fn rename_folder(&mut self, folder_id: &str, new_name: String) -> Result<String, Error> {
+        let index = self.folder_index(folder_id)?;
+        let new_name = self.validate_folder_name(&new_name, Some(folder_id))?;
+        Ok(std::mem::replace(&mut self.folders[index].name, new_name))
+    }
+
+    // Copies the folder's files, patterns and color into a new sibling folder;
+    // subfolders are not cloned. Returns the id of the clone.
+    pub fn clone_folder(&mut self, folder_id: &str, new_name: String) -> Result<String, Error> {
+        let source = self.folders[self.folder_index(folder_id)?].clone();
+        let new_name = self.validate_folder_name(&new_name, None)?;
+
+        let clone_id = self.generate_folder_id();
+        let mut folder = Folder::new(clone_id.clone(), new_name);
+        folder.files = source.files;
+        folder.patterns = source.patterns;
+        folder.color = source.color;
+        folder.parent_id = source.parent_id;
+
+        self.folders.push(folder);
+        Ok(clone_id)
+    }
+
+    // Imports a JSON array of `{ "name", "files", "patterns", "color" }`
+    // objects as top-level folders. Nothing is imported unless every name is
+    // valid. Returns the ids of the new folders.
+    pub fn import_from_json(&mut self, json: &str, workspace: &Workspace) -> Result<Vec<String>, Error> {
+        let imports: Vec<FolderImport> = serde_json::from_str(json).map_err(|e| Error::InvalidFolderImport {
+            name: None,
+            reason: e.to_string(),
+        })?;
+
+        let mut staged = self.clone();
+        let mut imported = Vec::new();
+        for import in &imports {
+            for pattern in &import.patterns {
+                directory::validate_glob(pattern.strip_prefix('!').unwrap_or(pattern))?;
+            }
+            let name = staged
+                .validate_folder_name(&import.name, None)
+                .map_err(|e| Error::InvalidFolderImport {
+                    name: Some(import.name.clone()),
+                    reason: e.to_string(),
+                })?;
+
+            let folder_id = staged.generate_folder_id();
+            imported.push(folder_id.clone());
+            let mut folder = Folder::new(folder_id, name);
+            for file_path in &import.files {
+                folder.add_file(workspace.normalize(file_path));
             }
+            for pattern in &import.patterns {
+                folder.add_pattern(pattern.clone());
+            }
+            folder.color = import.color.clone();
+            staged.folders.push(folder);
+        }
+
+        *self = staged;
+        Ok(imported)
+    }
+
+    pub fn add_file_to_folder(&mut self, folder_id: &str, file_path: String) -> Result<(), Error> {
+        let index = self.folder_index(folder_id)?;
+        if self.folders[index].add_file(file_path.clone()) {
+            Ok(())
         } else {
-            Err("Folder not found".to_string())
+            Err(Error::FileAlreadyInFolder {
+                folder: folder_id.to_string(),
+                path: file_path,
+            })
         }
     }
 
-    pub fn remove_file_from_folder(&mut self, folder_id: &str, file_path: &str) -> Result<String, String> {
-        if let Some(folder) = self.folders.iter_mut().find(|f| f.id == folder_id) {
-            if folder.remove_file(file_path) {
-                Ok(format!("File '{}' removed from folder '{}'", file_path, folder.name))
-            } else {
-                Err("File not found in folder".to_string())
-            }
+    pub fn remove_file_from_folder(&mut self, folder_id: &str, file_path: &str) -> Result<(), Error> {
+        let index = self.folder_index(folder_id)?;
+        if self.folders[index].remove_file(file_path) {
+            Ok(())
         } else {
-            Err("Folder not found".to_string())
+            Err(Error::FileNotInFolder {
+                folder: folder_id.to_string(),
+                path: file_path.to_string(),
+            })
+        }
+    }
+
+    // Rewrites the files of every folder; paths that end up the same are kept once
+    pub fn map_files(&mut self, map: impl Fn(&str) -> String) {
+        for folder in &mut self.folders {
+            let mut files = Vec::new();
+            for file in &folder.files {
+                let file = map(file);
+                if !files.contains(&file) {
+                    files.push(file);
+                }
+            }
+            folder.files = files;
         }
     }
 
@@ -92,6 +273,13 @@ impl FolderManager {
         self.folders.iter_mut().find(|f| f.id == folder_id)
     }
 
+    fn folder_index(&self, folder_id: &str) -> Result<usize, Error> {
+        self.folders
+            .iter()
+            .position(|f| f.id == folder_id)
+            .ok_or_else(|| Error::FolderNotFound { folder: folder_id.to_string() })
+    }
+
     pub fn list_folders(&self) -> &Vec<Folder> {
         &self.folders
     }
@@ -100,41 +288,102 @@ impl FolderManager {
         self.folders.len()
     }
 
-    pub fn copy_folder_contents(&self, folder_id: &str) -> Result<String, String> {
-        if let Some(folder) = self.get_folder(folder_id) {
-            let mut copied_files = Vec::new();
+    pub fn add_pattern_to_folder(&mut self, folder_id: &str, pattern: String) -> Result<(), Error> {
+        directory::validate_glob(pattern.strip_prefix('!').unwrap_or(&pattern))?;
 
-            for file_path in &folder.files {
-                match fs::read_to_string(file_path) {
-                    Ok(content) => {
-                        let copied_file = CopiedFile::new(
-                            file_path.clone(),
-                            file_path.clone(),
-                            content,
-                        );
-                        copied_files.push(copied_file);
-                    }
-                    Err(_) => {
-                        // Skip files that can't be read
-                        continue;
-                    }
+        let index = self.folder_index(folder_id)?;
+        if self.folders[index].add_pattern(pattern.clone()) {
+            Ok(())
+        } else {
+            Err(Error::PatternAlreadyInFolder {
+                folder: folder_id.to_string(),
+                pattern,
+            })
+        }
+    }
+
+    pub fn remove_pattern_from_folder(&mut self, folder_id: &str, pattern: &str) -> Result<(), Error> {
+        let index = self.folder_index(folder_id)?;
+        if self.folders[index].remove_pattern(pattern) {
+            Ok(())
+        } else {
+            Err(Error::PatternNotInFolder {
+                folder: folder_id.to_string(),
+                pattern: pattern.to_string(),
+            })
+        }
+    }
+
+    // The folder's explicit files followed by the files its patterns match
+    // under the workspace root. Exclude patterns only filter pattern matches.
+    pub fn resolve_folder_files(&self, folder_id: &str, workspace: &Workspace) -> Result<Vec<String>, Error> {
+        let folder = &self.folders[self.folder_index(folder_id)?];
+        let mut files = folder.files.clone();
+
+        let (include, exclude) = folder.split_patterns();
+        if !include.is_empty() {
+            let root = workspace.root().to_string_lossy();
+            for path in directory::find_files(&root, &include, &exclude)? {
+                let path = workspace.normalize(&path);
+                if !files.contains(&path) {
+                    files.push(path);
                 }
             }
+        }
+        Ok(files)
+    }
 
-            if copied_files.is_empty() {
-                return Err("No readable files found in folder".to_string());
+    // With `recursive`, the files of every subfolder follow the folder's own
+    pub fn resolve_folder_tree_files(&self, folder_id: &str, workspace: &Workspace, recursive: bool) -> Result<Vec<String>, Error> {
+        let mut files = self.resolve_folder_files(folder_id, workspace)?;
+        if recursive {
+            for descendant in self.descendants(folder_id) {
+                for file in self.resolve_folder_files(&descendant, workspace)? {
+                    if !files.contains(&file) {
+                        files.push(file);
+                    }
+                }
             }
+        }
+        Ok(files)
+    }
 
-            let combined = copied_files
-                .iter()
-                .map(|f| format!("{}\n\n{}", f.display_path, f.content))
-                .collect::<Vec<_>>()
-                .join("\n\n---\n\n");
+    pub fn copy_folder_contents(
+        &self,
+        folder_id: &str,
+        workspace: &Workspace,
+        recursive: bool,
+        formatter: &dyn OutputFormatter,
+    ) -> Result<String, Error> {
+        let copied_files = self.collect_folder_files(folder_id, workspace, recursive)?;
+        Ok(formatter.render(&copied_files))
+    }
 
-            Ok(combined)
-        } else {
-            Err("Folder not found".to_string())
+    pub fn collect_folder_files(&self, folder_id: &str, workspace: &Workspace, recursive: bool) -> Result<Vec<CopiedFile>, Error> {
+        let mut copied_files = Vec::new();
+
+        for file_path in self.resolve_folder_tree_files(folder_id, workspace, recursive)? {
+            match fs::read_to_string(workspace.absolute(&file_path)) {
+                Ok(content) => {
+                    let copied_file = CopiedFile::new(
+                        file_path.clone(),
+                        file_path,
+                        content,
+                    );
+                    copied_files.push(copied_file);
+                }
+                Err(_) => {
+                    // Skip files that can't be read
+                    continue;
+                }
+            }
         }
+
+        if copied_files.is_empty() {
+            return Err(Error::NoReadableFiles { folder: Some(folder_id.to_string()) });
+        }
+
+        Ok(copied_files)
     }
 
     pub fn find_folders_containing_file(&self, file_path: &str) -> Vec<&Folder> {
@@ -148,60 +397,96 @@ impl FolderManager {
         self.folders.iter().find(|f| f.name == name)
     }
 
-    pub fn set_folder_color(&mut self, folder_id: &str, color: Option<String>) -> Result<String, String> {
-        if let Some(folder) = self.folders.iter_mut().find(|f| f.id == folder_id) {
-            folder.color = color.clone();
-            let message = if let Some(color_value) = color {
-                format!("Color '{}' set for folder '{}'", color_value, folder.name)
-            } else {
-                format!("Color removed from folder '{}'", folder.name)
-            };
-            Ok(message)
-        } else {
-            Err("Folder not found".to_string())
-        }
+    pub fn set_folder_color(&mut self, folder_id: &str, color: Option<String>) -> Result<(), Error> {
+        let index = self.folder_index(folder_id)?;
+        self.folders[index].color = color;
+        Ok(())
     }
 
     pub fn get_total_files_count(&self) -> usize {
         self.folders.iter().map(|f| f.file_count()).sum()
     }
 
-    pub fn validate_folder_name(&self, name: &str, exclude_id: Option<&str>) -> Result<(), String> {
-        if name.trim().is_empty() {
-            return Err("Folder name cannot be empty".to_string());
+    // The single check for every folder name that enters the state. Returns the
+    // name as it should be stored: trimmed and in Unicode NFC, so visually
+    // identical names compare equal. Duplicates are detected ignoring case.
+    pub fn validate_folder_name(&self, name: &str, exclude_id: Option<&str>) -> Result<String, Error> {
+        let name: String = name.trim().nfc().collect();
+        let invalid = |reason: String| Error::InvalidFolderName {
+            name: name.clone(),
+            reason,
+        };
+
+        if name.is_empty() {
+            return Err(invalid("Folder name cannot be empty".to_string()));
         }
 
-        if name.len() > 100 {
-            return Err("Folder name is too long (max 100 characters)".to_string());
+        if name.chars().count() > MAX_FOLDER_NAME_LEN {
+            return Err(invalid(format!("Folder name is too long (max {} characters)", MAX_FOLDER_NAME_LEN)));
         }
 
         let invalid_chars = ['/', '\\', ':', '*', '?', '"', '<', '>', '|'];
-        if name.chars().any(|c| invalid_chars.contains(&c)) {
-            return Err("Folder name contains invalid characters".to_string());
+        if name.chars().any(|c| invalid_chars.contains(&c) || c.is_control()) {
+            return Err(invalid("Folder name contains invalid characters".to_string()));
         }
 
-        if let Some(existing) = self.folders.iter().find(|f| f.name == name) {
-            if let Some(exclude_id) = exclude_id {
-                if existing.id != exclude_id {
-                    return Err("Folder with this name already exists".to_string());
-                }
-            } else {
-                return Err("Folder with this name already exists".to_string());
-            }
+        let lowercase = name.to_lowercase();
+        let duplicate = self
+            .folders
+            .iter()
+            .filter(|f| Some(f.id.as_str()) != exclude_id)
+            .any(|f| f.name.nfc().collect::<String>().to_lowercase() == lowercase);
+        if duplicate {
+            return Err(Error::FolderExists { name });
         }
 
-        Ok(())
+        Ok(name)
     }
 
-    fn generate_folder_id(&self) -> String {
-        use std::time::{SystemTime, UNIX_EPOCH};
-
-        let timestamp = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_millis();
+    // Counter-based, skipping ids already taken (such as the timestamp ids of
+    // older versions), so ids are unique within the state
+    fn generate_folder_id(&mut self) -> String {
+        loop {
+            self.next_folder_id += 1;
+            let id = format!("folder_{}", self.next_folder_id);
+            if self.get_folder(&id).is_none() {
+                return id;
+            }
+        }
+    }
 
-        format!("folder_{}", timestamp)
+    // Accepts a folder id, a folder name, or a prefix of exactly one folder's
+    // name, matched without regard to case. The key is trimmed and NFC-normalized
+    // like stored names. A key that is one folder's id and another folder's name
+    // is ambiguous rather than resolved to the id.
+    pub fn resolve_folder_key(&self, key: &str) -> Result<String, Error> {
+        let key: String = key.trim().nfc().collect();
+        let key_lower = key.to_lowercase();
+        let name_lower = |folder: &Folder| folder.name.nfc().collect::<String>().to_lowercase();
+
+        let exact: Vec<&Folder> = self.folders.iter().filter(|f| name_lower(f) == key_lower).collect();
+        let candidates = match self.get_folder(&key) {
+            Some(folder) => {
+                let mut candidates = vec![folder];
+                candidates.extend(exact.into_iter().filter(|f| f.id != folder.id));
+                candidates
+            }
+            None if exact.is_empty() => self
+                .folders
+                .iter()
+                .filter(|f| name_lower(f).starts_with(&key_lower))
+                .collect(),
+            None => exact,
+        };
+
+        match candidates.as_slice() {
+            [] => Err(Error::FolderNotFound { folder: key }),
+            [folder] => Ok(folder.id.clone()),
+            _ => Err(Error::AmbiguousFolder {
+                matches: candidates.iter().map(|f| (f.id.clone(), f.name.clone())).collect(),
+                key,
+            }),
+        }
     }
 }
 
@@ -214,6 +499,7 @@ impl Default for FolderManager {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::paths::DisplayMode;
 
     #[test]
     fn test_create_folder() {
@@ -235,8 +521,7 @@ mod tests {
         manager.create_folder("Test Folder".to_string(), vec![]).unwrap();
 
         let result = manager.create_folder("Test Folder".to_string(), vec![]);
-        assert!(result.is_err());
-        assert!(result.unwrap_err().contains("already exists"));
+        assert_eq!(result, Err(Error::FolderExists { name: "Test Folder".to_string() }));
     }
 
     #[test]
@@ -321,4 +606,255 @@ mod tests {
         let folders_with_nonexistent = manager.find_folders_containing_file("nonexistent.rs");
         assert_eq!(folders_with_nonexistent.len(), 0);
     }
+
+    #[test]
+    fn test_pattern_folders_resolve_against_root() {
+        let root = std::env::temp_dir().join(format!("copy-path-folders-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        for path in ["src/api/users.rs", "src/api/orders.rs", "src/api/tests/users.rs", "src/main.rs"] {
+            let path = root.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "fn handler() {}").unwrap();
+        }
+        let workspace = Workspace::new(&root.to_string_lossy(), false, DisplayMode::Relative);
+
+        let mut manager = FolderManager::new();
+        manager.create_folder("API".to_string(), vec!["src/main.rs".to_string()]).unwrap();
+        let folder_id = manager.folders[0].id.clone();
+        manager.add_pattern_to_folder(&folder_id, "src/api/**/*.rs".to_string()).unwrap();
+        manager.add_pattern_to_folder(&folder_id, "!**/tests/**".to_string()).unwrap();
+
+        let resolved = manager.resolve_folder_files(&folder_id, &workspace).unwrap();
+        assert_eq!(resolved, vec!["src/main.rs", "src/api/orders.rs", "src/api/users.rs"]);
+
+        // Files created later are picked up the next time the folder is used
+        fs::write(root.join("src/api/billing.rs"), "fn bill() {}").unwrap();
+        assert_eq!(manager.collect_folder_files(&folder_id, &workspace, false).unwrap().len(), 4);
+
+        assert!(matches!(
+            manager.add_pattern_to_folder(&folder_id, "src/api/**/*.rs".to_string()),
+            Err(Error::PatternAlreadyInFolder { .. })
+        ));
+        assert!(matches!(
+            manager.add_pattern_to_folder(&folder_id, "src/[".to_string()),
+            Err(Error::InvalidGlob { .. })
+        ));
+        manager.remove_pattern_from_folder(&folder_id, "!**/tests/**").unwrap();
+        assert_eq!(manager.resolve_folder_files(&folder_id, &workspace).unwrap().len(), 5);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    // Test folders get their names as ids so assertions read clearly
+    fn create(manager: &mut FolderManager, parent: Option<&str>, name: &str, files: &[&str]) {
+        let files = files.iter().map(|f| f.to_string()).collect();
+        match parent {
+            Some(parent) => manager.create_subfolder(parent, name.to_string(), files).unwrap(),
+            None => manager.create_folder(name.to_string(), files).unwrap(),
+        };
+        manager.folders.last_mut().unwrap().id = name.to_string();
+    }
+
+    fn nested_manager() -> FolderManager {
+        let mut manager = FolderManager::new();
+        create(&mut manager, None, "api", &["api.rs"]);
+        create(&mut manager, Some("api"), "users", &["users.rs"]);
+        create(&mut manager, Some("users"), "admin", &["admin.rs", "api.rs"]);
+        create(&mut manager, Some("api"), "orders", &["orders.rs"]);
+        create(&mut manager, None, "docs", &["README.md"]);
+        manager
+    }
+
+    #[test]
+    fn test_folder_tree_and_descendants() {
+        let manager = nested_manager();
+
+        let tree: Vec<(usize, &str)> = manager
+            .folder_tree()
+            .iter()
+            .map(|(depth, folder)| (*depth, folder.name.as_str()))
+            .collect();
+        assert_eq!(tree, vec![(0, "api"), (1, "users"), (2, "admin"), (1, "orders"), (0, "docs")]);
+        assert_eq!(manager.descendants("api"), vec!["users", "admin", "orders"]);
+
+        let mut manager = manager;
+        assert_eq!(
+            manager.create_subfolder("missing", "x".to_string(), vec![]),
+            Err(Error::ParentFolderNotFound { folder: "missing".to_string() })
+        );
+    }
+
+    #[test]
+    fn test_recursive_files_include_descendants_once() {
+        let manager = nested_manager();
+        let workspace = Workspace::new(".", false, DisplayMode::Relative);
+
+        assert_eq!(manager.resolve_folder_tree_files("api", &workspace, false).unwrap(), vec!["api.rs"]);
+        assert_eq!(
+            manager.resolve_folder_tree_files("api", &workspace, true).unwrap(),
+            vec!["api.rs", "users.rs", "admin.rs", "orders.rs"]
+        );
+    }
+
+    #[test]
+    fn test_move_folder_prevents_cycles() {
+        let mut manager = nested_manager();
+
+        assert!(matches!(manager.move_folder("api", Some("admin")), Err(Error::FolderCycle { .. })));
+        assert!(matches!(manager.move_folder("api", Some("api")), Err(Error::FolderCycle { .. })));
+        assert!(matches!(manager.move_folder("api", Some("missing")), Err(Error::ParentFolderNotFound { .. })));
+
+        manager.move_folder("admin", Some("docs")).unwrap();
+        assert_eq!(manager.descendants("docs"), vec!["admin"]);
+        manager.move_folder("admin", None).unwrap();
+        assert_eq!(manager.get_folder("admin").unwrap().parent_id, None);
+    }
+
+    #[test]
+    fn test_delete_with_children() {
+        let mut manager = nested_manager();
+
+        let error = manager.delete_folder("users").unwrap_err();
+        assert!(matches!(error, Error::FolderHasSubfolders { subfolders: 1, .. }));
+        assert_eq!(manager.get_folder_count(), 5);
+
+        let deleted: Vec<String> = manager.delete_folder_recursive("api").unwrap().into_iter().map(|f| f.id).collect();
+        assert_eq!(deleted, vec!["api", "users", "admin", "orders"]);
+        let remaining: Vec<&str> = manager.folders.iter().map(|f| f.id.as_str()).collect();
+        assert_eq!(remaining, vec!["docs"]);
+    }
+
+    #[test]
+    fn test_folder_ids_are_unique() {
+        let mut manager = FolderManager::new();
+        for name in ["a", "b", "c"] {
+            manager.create_folder(name.to_string(), vec![]).unwrap();
+        }
+        let ids: Vec<&str> = manager.folders.iter().map(|f| f.id.as_str()).collect();
+        assert_eq!(ids, vec!["folder_1", "folder_2", "folder_3"]);
+
+        // Ids already in use are skipped
+        manager.next_folder_id = 0;
+        manager.folders[0].id = "folder_4".to_string();
+        manager.folders[1].id = "folder_1".to_string();
+        manager.create_folder("d".to_string(), vec![]).unwrap();
+        assert_eq!(manager.folders[3].id, "folder_2");
+
+        // Deleting one of several folders removes only that folder
+        manager.delete_folder("folder_3").unwrap();
+        let names: Vec<&str> = manager.folders.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(names, vec!["a", "b", "d"]);
+    }
+
+    #[test]
+    fn test_resolve_folder_key() {
+        let mut manager = FolderManager::new();
+        for name in ["API handlers", "Apps", "Docs"] {
+            manager.create_folder(name.to_string(), vec![]).unwrap();
+        }
+
+        assert_eq!(manager.resolve_folder_key("folder_2").unwrap(), "folder_2");
+        assert_eq!(manager.resolve_folder_key("Docs").unwrap(), "folder_3");
+        assert_eq!(manager.resolve_folder_key("docs").unwrap(), "folder_3");
+        assert_eq!(manager.resolve_folder_key("api").unwrap(), "folder_1");
+        assert_eq!(manager.resolve_folder_key("APPS").unwrap(), "folder_2");
+
+        let ambiguous = manager.resolve_folder_key("ap").unwrap_err();
+        assert_eq!(ambiguous.code(), "ambiguous_folder");
+        assert_eq!(ambiguous.to_string(), "Folder 'ap' is ambiguous: API handlers (folder_1), Apps (folder_2)");
+        assert_eq!(manager.resolve_folder_key("x"), Err(Error::FolderNotFound { folder: "x".to_string() }));
+    }
+
+    #[test]
+    fn test_folder_key_matching_an_id_and_a_name_is_ambiguous() {
+        let mut manager = FolderManager::new();
+        manager.create_folder("Docs".to_string(), vec![]).unwrap();
+        manager.create_folder("folder_1".to_string(), vec![]).unwrap();
+
+        let ambiguous = manager.resolve_folder_key("folder_1").unwrap_err();
+        assert_eq!(ambiguous.to_string(), "Folder 'folder_1' is ambiguous: Docs (folder_1), folder_1 (folder_2)");
+        assert_eq!(manager.resolve_folder_key("folder_2").unwrap(), "folder_2");
+    }
+
+    #[test]
+    fn test_folder_keys_are_normalized_like_names() {
+        let mut manager = FolderManager::new();
+        manager.create_folder("Caf\u{e9}".to_string(), vec![]).unwrap();
+
+        assert_eq!(manager.resolve_folder_key("Caf\u{65}\u{301}").unwrap(), "folder_1");
+        assert_eq!(manager.resolve_folder_key(" caf\u{65}\u{301} ").unwrap(), "folder_1");
+        assert_eq!(manager.resolve_folder_key("ca").unwrap(), "folder_1");
+    }
+
+    #[test]
+    fn test_folder_names_are_trimmed_and_normalized() {
+        let mut manager = FolderManager::new();
+        manager.create_folder("  Docs \t".to_string(), vec![]).unwrap();
+        assert_eq!(manager.folders[0].name, "Docs");
+
+        // "é" as e + combining acute accent is stored precomposed
+        manager.create_folder("Caf\u{65}\u{301}".to_string(), vec![]).unwrap();
+        assert_eq!(manager.folders[1].name, "Caf\u{e9}");
+        assert!(manager.create_folder("Caf\u{e9}".to_string(), vec![]).is_err());
+    }
+
+    #[test]
+    fn test_folder_name_length_is_counted_in_characters() {
+        let manager = FolderManager::new();
+        assert!(manager.validate_folder_name(&"é".repeat(MAX_FOLDER_NAME_LEN), None).is_ok());
+        assert!(manager.validate_folder_name(&"a".repeat(MAX_FOLDER_NAME_LEN + 1), None).is_err());
+
+        let mut manager = manager;
+        let error = manager.create_folder("x".repeat(500), vec![]).unwrap_err();
+        assert!(matches!(error, Error::InvalidFolderName { ref reason, .. } if reason.contains("too long")));
+    }
+
+    #[test]
+    fn test_folder_names_reject_invalid_characters() {
+        let mut manager = FolderManager::new();
+        for name in ["a/b", "a\\b", "a:b", "a*b", "a?b", "a\"b", "a<b", "a>b", "a|b", "a\nb"] {
+            assert!(manager.create_folder(name.to_string(), vec![]).is_err(), "{:?}", name);
+        }
+        assert_eq!(manager.get_folder_count(), 0);
+    }
+
+    #[test]
+    fn test_duplicate_folder_names_ignore_case() {
+        let mut manager = FolderManager::new();
+        manager.create_folder("API".to_string(), vec![]).unwrap();
+        manager.create_folder("Docs".to_string(), vec![]).unwrap();
+
+        assert!(matches!(manager.create_folder("api".to_string(), vec![]), Err(Error::FolderExists { .. })));
+        assert!(matches!(manager.rename_folder("folder_2", "Api ".to_string()), Err(Error::FolderExists { .. })));
+        // Changing only the case of a folder's own name is allowed
+        manager.rename_folder("folder_1", "api".to_string()).unwrap();
+        assert_eq!(manager.folders[0].name, "api");
+        assert!(manager.rename_folder("folder_1", "a/b".to_string()).is_err());
+    }
+
+    #[test]
+    fn test_clone_and_import_validate_names() {
+        let mut manager = FolderManager::new();
+        manager.create_folder("API".to_string(), vec!["api.rs".to_string()]).unwrap();
+
+        assert!(manager.clone_folder("folder_1", "api".to_string()).is_err());
+        assert_eq!(manager.clone_folder("folder_1", " API copy ".to_string()).unwrap(), "folder_2");
+        assert_eq!(manager.folders[1].name, "API copy");
+        assert_eq!(manager.folders[1].files, vec!["api.rs"]);
+
+        let workspace = Workspace::new("/home/me/proj", false, DisplayMode::Relative);
+        let error = manager
+            .import_from_json(r#"[{ "name": "Docs" }, { "name": "docs" }]"#, &workspace)
+            .unwrap_err();
+        assert_eq!(error.to_string(), "Cannot import 'docs': Folder with the name 'docs' already exists");
+        assert_eq!(manager.get_folder_count(), 2);
+
+        let imported = manager
+            .import_from_json(r#"[{ "name": " Docs", "files": ["./README.md"], "patterns": ["docs/**"] }]"#, &workspace)
+            .unwrap();
+        assert_eq!(imported, vec!["folder_3"]);
+        assert_eq!(manager.folders[2].name, "Docs");
+        assert_eq!(manager.folders[2].files, vec!["README.md"]);
+        assert_eq!(manager.folders[2].patterns, vec!["docs/**"]);
+    }
 }
@@ -0,0 +1,161 @@
+use std::path::Path;
+
+pub fn detect_language(file_path: &str, content: &str) -> Option<&'static str> {
+    detect_from_file_name(file_path)
+        .or_else(|| detect_from_extension(file_path))
+        .or_else(|| detect_from_shebang(content))
+}
+
+fn detect_from_file_name(file_path: &str) -> Option<&'static str> {
+    let file_name = Path::new(file_path).file_name()?.to_str()?;
+
+    let language = match file_name {
+        "Dockerfile" | "Containerfile" => "dockerfile",
+        "Makefile" | "makefile" | "GNUmakefile" => "makefile",
+        "CMakeLists.txt" => "cmake",
+        "Cargo.lock" | "Pipfile" | "poetry.lock" => "toml",
+        "Gemfile" | "Rakefile" => "ruby",
+        "Jenkinsfile" => "groovy",
+        ".bashrc" | ".bash_profile" | ".profile" => "bash",
+        ".zshrc" | ".zprofile" => "zsh",
+        ".gitignore" | ".dockerignore" | ".ignore" => "gitignore",
+        ".editorconfig" => "ini",
+        _ => return None,
+    };
+
+    Some(language)
+}
+
+fn detect_from_extension(file_path: &str) -> Option<&'static str> {
+    let extension = Path::new(file_path)
+        .extension()?
+        .to_str()?
+        .to_ascii_lowercase();
+
+    let language = match extension.as_str() {
+        "rs" => "rust",
+        "py" | "pyi" | "pyw" => "python",
+        "js" | "mjs" | "cjs" => "javascript",
+        "jsx" => "jsx",
+        "ts" | "mts" | "cts" => "typescript",
+        "tsx" => "tsx",
+        "go" => "go",
+        "java" => "java",
+        "kt" | "kts" => "kotlin",
+        "scala" | "sc" => "scala",
+        "swift" => "swift",
+        "c" | "h" => "c",
+        "cc" | "cpp" | "cxx" | "hh" | "hpp" | "hxx" => "cpp",
+        "cs" => "csharp",
+        "fs" | "fsx" => "fsharp",
+        "rb" => "ruby",
+        "php" => "php",
+        "pl" | "pm" => "perl",
+        "lua" => "lua",
+        "dart" => "dart",
+        "ex" | "exs" => "elixir",
+        "erl" | "hrl" => "erlang",
+        "hs" => "haskell",
+        "ml" | "mli" => "ocaml",
+        "clj" | "cljs" | "cljc" | "edn" => "clojure",
+        "zig" => "zig",
+        "nim" => "nim",
+        "r" => "r",
+        "jl" => "julia",
+        "sh" => "sh",
+        "bash" => "bash",
+        "zsh" => "zsh",
+        "fish" => "fish",
+        "ps1" | "psm1" => "powershell",
+        "bat" | "cmd" => "batch",
+        "sql" => "sql",
+        "html" | "htm" => "html",
+        "css" => "css",
+        "scss" => "scss",
+        "sass" => "sass",
+        "less" => "less",
+        "vue" => "vue",
+        "svelte" => "svelte",
+        "json" | "jsonc" => "json",
+        "yaml" | "yml" => "yaml",
+        "toml" => "toml",
+        "xml" | "svg" | "plist" => "xml",
+        "ini" | "cfg" | "conf" => "ini",
+        "md" | "markdown" => "markdown",
+        "tex" => "latex",
+        "graphql" | "gql" => "graphql",
+        "proto" => "protobuf",
+        "tf" | "hcl" => "hcl",
+        "nix" => "nix",
+        "diff" | "patch" => "diff",
+        "dockerfile" => "dockerfile",
+        _ => return None,
+    };
+
+    Some(language)
+}
+
+fn detect_from_shebang(content: &str) -> Option<&'static str> {
+    let first_line = content.lines().next()?.strip_prefix("#!")?;
+    let mut parts = first_line.split_whitespace();
+    let mut interpreter = parts.next()?.rsplit('/').next()?;
+
+    // `#!/usr/bin/env -S python3 -u` names the interpreter after env and its flags
+    if interpreter == "env" {
+        interpreter = parts.find(|part| !part.starts_with('-'))?;
+    }
+
+    // Strip version suffixes such as python3 or python3.11
+    let interpreter = interpreter.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.');
+
+    let language = match interpreter {
+        "python" | "pypy" => "python",
+        "node" | "nodejs" => "javascript",
+        "deno" | "bun" | "ts-node" => "typescript",
+        "bash" => "bash",
+        "sh" | "dash" | "ash" => "sh",
+        "zsh" => "zsh",
+        "fish" => "fish",
+        "ruby" => "ruby",
+        "perl" => "perl",
+        "php" => "php",
+        "lua" => "lua",
+        "Rscript" => "r",
+        "pwsh" => "powershell",
+        _ => return None,
+    };
+
+    Some(language)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_from_extension() {
+        assert_eq!(detect_language("src/lib.rs", ""), Some("rust"));
+        assert_eq!(detect_language("app/main.PY", ""), Some("python"));
+        assert_eq!(detect_language("web/index.tsx", ""), Some("tsx"));
+        assert_eq!(detect_language("notes.unknown", ""), None);
+    }
+
+    #[test]
+    fn test_detect_from_file_name() {
+        assert_eq!(detect_language("docker/Dockerfile", ""), Some("dockerfile"));
+        assert_eq!(detect_language("Makefile", ""), Some("makefile"));
+    }
+
+    #[test]
+    fn test_detect_from_shebang() {
+        assert_eq!(detect_language("bin/tool", "#!/usr/bin/env python3\nprint(1)"), Some("python"));
+        assert_eq!(detect_language("bin/run", "#!/bin/bash\necho hi"), Some("bash"));
+        assert_eq!(detect_language("bin/srv", "#!/usr/bin/env -S node --harmony\n"), Some("javascript"));
+        assert_eq!(detect_language("bin/data", "no shebang here"), None);
+    }
+
+    #[test]
+    fn test_extension_takes_precedence_over_shebang() {
+        assert_eq!(detect_language("script.rb", "#!/usr/bin/env python\n"), Some("ruby"));
+    }
+}
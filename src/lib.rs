@@ -2,162 +2,1063 @@ use zed_extension_api as zed;
 
 mod models;
 mod clipboard;
+mod commands;
+mod directory;
+mod error;
 mod folders;
+mod language;
+mod output;
+mod paths;
+mod response;
+mod state;
+mod storage;
+mod templates;
+mod tokens;
 
-use models::{ExtensionState, FileSelection};
-use clipboard::ClipboardManager;
-use folders::FolderManager;
+use clipboard::{ClipboardBuffer, EntryStatus, LimitUnit, MovedEntry, SizeTarget, SortKey, TrimStrategy};
+use commands::{BatchMode, BatchStep, Command, CommandHelp};
+use directory::DirectoryOptions;
+use error::Error;
+use models::{CopiedFile, FileSelection, Folder};
+use output::{OutputFormat, OutputFormatter};
+use paths::DisplayMode;
+use response::{Response, ResponseFormat};
+use serde_json::{json, Value};
+use state::ExtensionState;
+use storage::Storage;
+use templates::OutputTemplate;
+use tokens::{BudgetMode, TokenBudget, TokenEstimator};
 
 struct CopyPathWithCodeExtension {
-    clipboard_manager: ClipboardManager,
-    folder_manager: FolderManager,
+    state: ExtensionState,
+    storage: Storage,
 }
 
 impl CopyPathWithCodeExtension {
     fn new() -> Self {
+        Self::with_storage(Storage::in_work_dir())
+    }
+
+    fn with_storage(mut storage: Storage) -> Self {
+        let mut loaded = storage.load();
+        if let Some(warning) = loaded.warning {
+            eprintln!("Extension warning: {}", warning);
+        }
+        for error in loaded.state.templates.take_load_errors() {
+            eprintln!("Extension warning: stored template kept but unusable until redefined: {}", error);
+        }
+
         Self {
-            clipboard_manager: ClipboardManager::new(),
-            folder_manager: FolderManager::new(),
+            state: loaded.state,
+            storage,
         }
     }
 
-    fn copy_current_file(&mut self, file_path: String, content: String, selection: Option<FileSelection>) -> Result<String, String> {
-        let combined_content = self.clipboard_manager.copy_file_with_content(file_path, content, selection)?;
+    fn save_state(&self) -> Result<(), Error> {
+        self.storage.save(&self.state)
+    }
+
+    fn copy_current_file(&mut self, file_path: String, content: String, selection: Option<FileSelection>, buffer: Option<&str>, formatter: &dyn OutputFormatter) -> Result<Response, Error> {
+        let buffer = buffer.unwrap_or(&self.state.clipboard.active_buffer).to_string();
+        let previous = self.state.clipboard.buffers.get(&buffer).cloned();
+        let file_path = self.state.workspace().normalize(&file_path);
 
-        // In a real Zed extension, we would use the actual clipboard API
-        // For now, we return the combined content that would be copied
-        Ok(format!("Copied {} files to clipboard", self.clipboard_manager.get_file_count()))
+        self.state.clipboard.copy_file_to_buffer(&buffer, file_path.clone(), content, selection)?;
+        let mut response = self.enforce_token_budget(buffer, previous, formatter, String::new())?;
+        response.data["path"] = json!(file_path);
+        Ok(response)
     }
 
-    fn clear_clipboard(&mut self) -> String {
-        self.clipboard_manager.clear();
-        "Clipboard cleared".to_string()
+    fn copy_directory(&mut self, path: &str, options: &DirectoryOptions, buffer: Option<&str>, formatter: &dyn OutputFormatter) -> Result<Response, Error> {
+        let workspace = self.state.workspace();
+        let path = workspace.normalize(path);
+        let mut contents = directory::collect_directory(&workspace.absolute(&path).to_string_lossy(), options)?;
+        if contents.files.is_empty() {
+            return Err(Error::NoFilesToCopy { path });
+        }
+
+        let buffer = buffer.unwrap_or(&self.state.clipboard.active_buffer).to_string();
+        let previous = self.state.clipboard.buffers.get(&buffer).cloned();
+        for (file_path, _) in contents.files.iter_mut() {
+            *file_path = workspace.normalize(file_path);
+        }
+        let count = self.state.clipboard.copy_files_to_buffer(&buffer, contents.files, format!("copy directory {}", path))?;
+
+        let skipped: Vec<(String, &directory::SkipReason)> = contents
+            .skipped
+            .iter()
+            .map(|(skipped, reason)| (workspace.normalize(skipped), reason))
+            .collect();
+        let mut details = format!("\nAdded {} files from '{}'", count, path);
+        if !skipped.is_empty() {
+            details.push_str(&format!(", skipped {}:", skipped.len()));
+            for (skipped, reason) in &skipped {
+                details.push_str(&format!("\n- {} ({})", skipped, reason.describe()));
+            }
+        }
+
+        let mut response = self.enforce_token_budget(buffer, previous, formatter, details)?;
+        response.data["path"] = json!(path);
+        response.data["added"] = json!(count);
+        response.data["skipped"] = skipped
+            .iter()
+            .map(|(skipped, reason)| json!({ "path": skipped, "reason": reason.name(), "detail": reason.describe() }))
+            .collect();
+        Ok(response)
     }
 
-    fn create_folder(&mut self, name: String, initial_files: Vec<String>) -> Result<String, String> {
-        self.folder_manager.create_folder(name, initial_files)
+    // Checks the buffer a copy just changed against the token budget, putting
+    // back `previous` when the budget refuses it
+    fn enforce_token_budget(&mut self, buffer: String, previous: Option<ClipboardBuffer>, formatter: &dyn OutputFormatter, details: String) -> Result<Response, Error> {
+        let copied = self.state.clipboard.buffer(&buffer)?;
+        let combined_content = copied.render(formatter);
+        let tokens = self.state.token_estimator.count(&combined_content);
+
+        let exceeded = self.state.token_budget.and_then(|budget| {
+            budget
+                .check(self.state.token_estimator, tokens, &copied.copied_files)
+                .map(|message| (budget, message))
+        });
+        let response = Response::new(
+            format!("Copied {} files to clipboard (~{} tokens){}", copied.len(), tokens, details),
+            json!({ "buffer": buffer, "files": copied.len(), "tokens": tokens }),
+        );
+
+        match exceeded {
+            Some((budget, reason)) if budget.mode == BudgetMode::Refuse => {
+                // Put the buffer back exactly as it was, history included
+                match previous {
+                    Some(previous) => self.state.clipboard.buffers.insert(buffer, previous),
+                    None => self.state.clipboard.buffers.remove(&buffer),
+                };
+                Err(Error::BudgetExceeded {
+                    tokens,
+                    limit: budget.limit,
+                    reason,
+                })
+            }
+            // In a real Zed extension, we would use the actual clipboard API
+            // For now, we return the combined content that would be copied
+            Some((_, reason)) => Ok(response.with_warning(reason)),
+            None => Ok(response),
+        }
     }
 
-    fn delete_folder(&mut self, folder_id: String) -> Result<String, String> {
-        self.folder_manager.delete_folder(&folder_id)
+    fn clear_clipboard(&mut self) -> Response {
+        self.state.clipboard.clear();
+        Response::new("Clipboard cleared", json!({ "buffer": self.state.clipboard.active_buffer }))
     }
 
-    fn rename_folder(&mut self, folder_id: String, new_name: String) -> Result<String, String> {
-        self.folder_manager.rename_folder(&folder_id, new_name)
+    fn list_clipboard(&self) -> Response {
+        let entries = self.state.clipboard.active().list_entries();
+        let message = if entries.is_empty() {
+            "No files copied".to_string()
+        } else {
+            entries.join("\n")
+        };
+
+        let files: Vec<Value> = self
+            .state
+            .clipboard
+            .get_files()
+            .iter()
+            .enumerate()
+            .map(|(index, file)| entry_data(index, file))
+            .collect();
+        Response::new(message, json!({ "buffer": self.state.clipboard.active_buffer, "entries": files }))
     }
 
-    fn add_file_to_folder(&mut self, folder_id: String, file_path: String) -> Result<String, String> {
-        self.folder_manager.add_file_to_folder(&folder_id, file_path)
+    fn clipboard_status(&self) -> Response {
+        let files = self.state.clipboard.get_files();
+        if files.is_empty() {
+            return Response::new("No files copied", json!({ "entries": [], "stale": 0 }));
+        }
+
+        let statuses = self.state.clipboard.active().check_staleness(&self.state.workspace());
+        let stale = statuses.iter().filter(|(_, status)| status.is_stale()).count();
+        let mut lines: Vec<String> = statuses
+            .iter()
+            .zip(files)
+            .enumerate()
+            .map(|(index, ((path, status), file))| {
+                format!(
+                    "{}. {} [{}] captured {}",
+                    index + 1,
+                    path,
+                    status.flag(),
+                    file.copied_at.format("%Y-%m-%d %H:%M:%S UTC")
+                )
+            })
+            .collect();
+
+        lines.push(if stale == 0 {
+            "All entries match the files on disk".to_string()
+        } else {
+            format!("{} of {} entries are stale; run refresh_clipboard to update them", stale, files.len())
+        });
+
+        let entries: Vec<Value> = statuses
+            .iter()
+            .zip(files)
+            .enumerate()
+            .map(|(index, ((_, status), file))| {
+                let mut entry = entry_data(index, file);
+                entry["status"] = json!(status.name());
+                entry["detail"] = json!(status.flag());
+                entry
+            })
+            .collect();
+        Response::new(lines.join("\n"), json!({ "entries": entries, "stale": stale }))
     }
 
-    fn remove_file_from_folder(&mut self, folder_id: String, file_path: String) -> Result<String, String> {
-        self.folder_manager.remove_file_from_folder(&folder_id, &file_path)
+    fn refresh_clipboard(&mut self) -> Response {
+        let workspace = self.state.workspace();
+        let results = self.state.clipboard.active_mut().refresh(&workspace);
+        if results.is_empty() {
+            return Response::new("No files copied", json!({ "updated": 0, "entries": [] }));
+        }
+
+        let updated = results.iter().filter(|(_, status)| *status == EntryStatus::Changed).count();
+        let mut lines = vec![format!("Refreshed {} of {} entries", updated, results.len())];
+        // Refreshing never reorders entries, so results line up with the files
+        let files = self.state.clipboard.get_files();
+        lines.extend(results.iter().zip(files).filter_map(|((path, status), file)| match status {
+            EntryStatus::Fresh => None,
+            EntryStatus::Changed if *path != file.display_path => {
+                Some(format!("- {}: updated, now {}", path, file.display_path))
+            }
+            EntryStatus::Changed => Some(format!("- {}: updated", path)),
+            other => Some(format!("- {}: {}, kept the old copy", path, other.flag())),
+        }));
+
+        let entries: Vec<Value> = results
+            .iter()
+            .zip(files)
+            .enumerate()
+            .map(|(index, ((path, status), file))| {
+                let mut entry = entry_data(index, file);
+                entry["previous_path"] = json!(path);
+                entry["status"] = json!(status.name());
+                entry["detail"] = json!(status.flag());
+                entry
+            })
+            .collect();
+        Response::new(lines.join("\n"), json!({ "updated": updated, "entries": entries }))
     }
 
-    fn copy_folder_contents(&mut self, folder_id: String) -> Result<String, String> {
-        let combined_content = self.folder_manager.copy_folder_contents(&folder_id)?;
+    fn move_clipboard_entry(&mut self, entry: &str, position: usize) -> Result<Response, Error> {
+        let entry = self.entry_key(entry);
+        let moved = self.state.clipboard.active_mut().move_entry(&entry, position)?;
+        Ok(moved_response(moved))
+    }
 
-        // In a real implementation, this would be copied to the system clipboard
-        Ok(format!("Copied folder contents to clipboard"))
+    fn move_clipboard_entry_up(&mut self, entry: &str) -> Result<Response, Error> {
+        let entry = self.entry_key(entry);
+        self.state.clipboard.active_mut().move_entry_up(&entry).map(moved_response)
+    }
+
+    fn move_clipboard_entry_down(&mut self, entry: &str) -> Result<Response, Error> {
+        let entry = self.entry_key(entry);
+        self.state.clipboard.active_mut().move_entry_down(&entry).map(moved_response)
     }
 
-    fn list_folders(&self) -> Vec<String> {
-        self.folder_manager
-            .list_folders()
+    // Entries are addressed by index or by path, given in any form
+    fn entry_key(&self, entry: &str) -> String {
+        match entry.parse::<usize>() {
+            Ok(_) => entry.to_string(),
+            Err(_) => self.state.workspace().normalize(entry),
+        }
+    }
+
+    fn clipboard_history(&self) -> Response {
+        let snapshots: Vec<_> = self.state.clipboard.active().history().collect();
+        let entries: Vec<String> = snapshots
+            .iter()
+            .enumerate()
+            .map(|(index, snapshot)| {
+                format!(
+                    "{}. {} - {} files (before {})",
+                    index + 1,
+                    snapshot.taken_at.format("%Y-%m-%d %H:%M:%S UTC"),
+                    snapshot.files.len(),
+                    snapshot.label
+                )
+            })
+            .collect();
+
+        let message = if entries.is_empty() {
+            "No clipboard history".to_string()
+        } else {
+            entries.join("\n")
+        };
+        let data: Vec<Value> = snapshots
+            .iter()
+            .enumerate()
+            .map(|(index, snapshot)| {
+                json!({
+                    "index": index + 1,
+                    "taken_at": snapshot.taken_at.to_rfc3339(),
+                    "files": snapshot.files.len(),
+                    "label": snapshot.label,
+                })
+            })
+            .collect();
+        Response::new(message, json!({ "entries": data }))
+    }
+
+    fn list_buffers(&self) -> Response {
+        let buffers = self.state.clipboard.list_buffers();
+        let message = buffers
             .iter()
-            .map(|folder| format!("{}: {} ({} files)", folder.id, folder.name, folder.file_count()))
-            .collect()
+            .map(|(name, count, active)| {
+                let marker = if *active { "* " } else { "  " };
+                format!("{}{} ({} files)", marker, name, count)
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        let data: Vec<Value> = buffers
+            .iter()
+            .map(|(name, count, active)| json!({ "name": name, "files": count, "active": active }))
+            .collect();
+        Response::new(message, json!({ "buffers": data }))
     }
 
-    fn get_status(&self) -> String {
-        format!(
-            "Clipboard: {} | Folders: {} | Total folder files: {}",
-            self.clipboard_manager.get_status_message(),
-            self.folder_manager.get_folder_count(),
-            self.folder_manager.get_total_files_count()
+    fn set_token_budget(&mut self, limit: Option<usize>, mode: Option<BudgetMode>) -> Response {
+        let Some(limit) = limit else {
+            self.state.token_budget = None;
+            return Response::new("Token budget disabled", json!({ "budget": null }));
+        };
+
+        let mode = mode.unwrap_or(BudgetMode::Warn);
+        let budget = TokenBudget::new(limit, mode);
+        self.state.token_budget = Some(budget);
+        Response::new(
+            format!("Token budget set to {} tokens ({})", limit, mode.name()),
+            json!({ "budget": budget_data(Some(&budget)) }),
         )
     }
 
-    fn handle_command(&mut self, command: &str, args: Vec<String>) -> Result<String, String> {
-        match command {
-            "copy_path_with_content" => {
-                if args.len() >= 2 {
-                    let file_path = args[0].clone();
-                    let content = args[1].clone();
-                    let selection = if args.len() >= 4 {
-                        let start_line: u32 = args[2].parse().map_err(|_| "Invalid start line")?;
-                        let end_line: u32 = args[3].parse().map_err(|_| "Invalid end line")?;
-                        let selected_content = if args.len() >= 5 { args[4].clone() } else { content.clone() };
-                        Some(FileSelection::new(start_line, end_line, selected_content))
-                    } else {
-                        None
-                    };
-                    self.copy_current_file(file_path, content, selection)
-                } else {
-                    Err("Missing arguments: file_path and content required".to_string())
-                }
-            }
-            "clear_clipboard" => {
-                Ok(self.clear_clipboard())
+    fn set_token_estimator(&mut self, estimator: TokenEstimator) -> Response {
+        self.state.token_estimator = estimator;
+        Response::new(
+            format!("Token estimator set to {}", estimator.name()),
+            json!({ "estimator": estimator.name() }),
+        )
+    }
+
+    fn token_report(&self) -> Response {
+        let estimator = self.state.token_estimator;
+        let files = self.state.clipboard.get_files();
+        let contributors = tokens::largest_contributors(estimator, files, files.len());
+        let total = self.state.clipboard_tokens();
+
+        let mut lines: Vec<String> = contributors
+            .iter()
+            .map(|(path, count)| format!("~{} tokens  {}", count, path))
+            .collect();
+        lines.push(format!(
+            "Total: ~{} tokens rendered as {} ({} estimator)",
+            total,
+            self.state.output_format.name(),
+            estimator.name()
+        ));
+        if let Some(budget) = &self.state.token_budget {
+            lines.push(format!("Budget: {} tokens ({})", budget.limit, budget.mode.name()));
+        }
+
+        let data: Vec<Value> = contributors
+            .iter()
+            .map(|(path, count)| json!({ "path": path, "tokens": count }))
+            .collect();
+        Response::new(
+            lines.join("\n"),
+            json!({
+                "files": data,
+                "total": total,
+                "format": self.state.output_format.name(),
+                "estimator": estimator.name(),
+                "budget": budget_data(self.state.token_budget.as_ref()),
+            }),
+        )
+    }
+
+    fn fit_to_budget(&mut self, limit: Option<usize>, unit: Option<LimitUnit>, strategies: Option<Vec<TrimStrategy>>, formatter: &dyn OutputFormatter) -> Result<Response, Error> {
+        let limit = match (limit, &self.state.token_budget) {
+            (Some(limit), _) => limit,
+            (None, Some(budget)) => budget.limit,
+            (None, None) => return Err(Error::invalid_argument("fit_to_budget", "limit", "required when no token budget is set")),
+        };
+        let unit = unit.unwrap_or_default().size_unit(self.state.token_estimator);
+        let strategies = strategies.unwrap_or_else(TrimStrategy::defaults);
+
+        let report = self.state.clipboard.active_mut().fit_to_budget(formatter, SizeTarget::new(limit, unit), &strategies);
+        Ok(Response::new(
+            report.summary(),
+            json!({
+                "limit": report.target.limit,
+                "unit": report.target.unit_name(),
+                "before": report.before,
+                "after": report.after,
+                "fits": report.fits(),
+                "strategies": strategies.iter().map(TrimStrategy::name).collect::<Vec<_>>(),
+                "cuts": report.cuts,
+            }),
+        ))
+    }
+
+    fn sort_clipboard(&mut self, key: SortKey) -> Response {
+        match key {
+            SortKey::Path => self.state.clipboard.active_mut().sort_by_path(),
+            SortKey::Time => self.state.clipboard.active_mut().sort_by_copy_time(),
+        }
+        Response::new(format!("Clipboard sorted by {}", key.name()), json!({ "key": key.name() }))
+    }
+
+    fn reverse_clipboard(&mut self) -> Response {
+        self.state.clipboard.active_mut().reverse();
+        Response::new("Clipboard order reversed", json!({ "files": self.state.clipboard.get_file_count() }))
+    }
+
+    fn set_preserve_position(&mut self, enabled: bool) -> Response {
+        self.state.clipboard.preserve_position_on_recopy = enabled;
+        let message = if enabled {
+            "Re-copied files keep their position"
+        } else {
+            "Re-copied files move to the end"
+        };
+        Response::new(message, json!({ "enabled": enabled }))
+    }
+
+    fn create_folder(&mut self, name: String, initial_files: Vec<String>) -> Result<Response, Error> {
+        let initial_files = self.normalize_paths(initial_files);
+        let folder_id = self.state.folder_manager.create_folder(name, initial_files)?;
+        Ok(Response::new(
+            format!("Folder '{}' created successfully", self.folder_name(&folder_id)),
+            json!({ "folder": self.folder_data(&folder_id) }),
+        ))
+    }
+
+    fn create_subfolder(&mut self, parent_id: String, name: String, initial_files: Vec<String>) -> Result<Response, Error> {
+        let parent_id = self.state.folder_manager.resolve_folder_key(&parent_id)?;
+        let initial_files = self.normalize_paths(initial_files);
+        let folder_id = self.state.folder_manager.create_subfolder(&parent_id, name, initial_files)?;
+        Ok(Response::new(
+            format!("Folder '{}' created successfully", self.folder_name(&folder_id)),
+            json!({ "folder": self.folder_data(&folder_id) }),
+        ))
+    }
+
+    fn delete_folder(&mut self, folder_id: String, recursive: bool) -> Result<Response, Error> {
+        let folder_id = self.state.folder_manager.resolve_folder_key(&folder_id)?;
+        let (message, deleted) = if recursive {
+            let deleted = self.state.folder_manager.delete_folder_recursive(&folder_id)?;
+            let message = format!(
+                "Folder '{}' and {} subfolders deleted successfully",
+                deleted[0].name,
+                deleted.len() - 1
+            );
+            (message, deleted)
+        } else {
+            let deleted = self.state.folder_manager.delete_folder(&folder_id)?;
+            (format!("Folder '{}' deleted successfully", deleted.name), vec![deleted])
+        };
+
+        let ids: Vec<&str> = deleted.iter().map(|folder| folder.id.as_str()).collect();
+        Ok(Response::new(message, json!({ "deleted": ids })))
+    }
+
+    fn move_folder(&mut self, folder_id: String, new_parent_id: &str) -> Result<Response, Error> {
+        let folder_id = self.state.folder_manager.resolve_folder_key(&folder_id)?;
+        let new_parent_id = match new_parent_id {
+            "root" => None,
+            key => Some(self.state.folder_manager.resolve_folder_key(key)?),
+        };
+        self.state.folder_manager.move_folder(&folder_id, new_parent_id.as_deref())?;
+
+        let parent_name = match &new_parent_id {
+            Some(parent_id) => format!("'{}'", self.folder_name(parent_id)),
+            None => "the top level".to_string(),
+        };
+        Ok(Response::new(
+            format!("Folder '{}' moved to {}", self.folder_name(&folder_id), parent_name),
+            json!({ "folder": self.folder_data(&folder_id) }),
+        ))
+    }
+
+    fn folder_tree(&self) -> Response {
+        let tree = self.state.folder_manager.folder_tree();
+        let message = if tree.is_empty() {
+            "No folders".to_string()
+        } else {
+            tree.iter()
+                .map(|(depth, folder)| {
+                    format!("{}{}: {} ({} files)", "  ".repeat(*depth), folder.id, folder.name, folder.file_count())
+                })
+                .collect::<Vec<_>>()
+                .join("\n")
+        };
+
+        // Parents come before their children, so the tree can be rebuilt from `parent_id`
+        let folders: Vec<Value> = tree
+            .iter()
+            .map(|(depth, folder)| {
+                let mut data = folder_summary(folder);
+                data["depth"] = json!(depth);
+                data
+            })
+            .collect();
+        Response::new(message, json!({ "folders": folders }))
+    }
+
+    fn rename_folder(&mut self, folder_id: String, new_name: String) -> Result<Response, Error> {
+        let folder_id = self.state.folder_manager.resolve_folder_key(&folder_id)?;
+        let old_name = self.state.folder_manager.rename_folder(&folder_id, new_name)?;
+        Ok(Response::new(
+            format!("Folder renamed from '{}' to '{}'", old_name, self.folder_name(&folder_id)),
+            json!({ "folder": self.folder_data(&folder_id), "old_name": old_name }),
+        ))
+    }
+
+    fn clone_folder(&mut self, folder_id: String, new_name: String) -> Result<Response, Error> {
+        let folder_id = self.state.folder_manager.resolve_folder_key(&folder_id)?;
+        let clone_id = self.state.folder_manager.clone_folder(&folder_id, new_name)?;
+        Ok(Response::new(
+            format!("Folder '{}' cloned as '{}'", self.folder_name(&folder_id), self.folder_name(&clone_id)),
+            json!({ "folder": self.folder_data(&clone_id), "source_id": folder_id }),
+        ))
+    }
+
+    fn import_folders(&mut self, json: &str) -> Result<Response, Error> {
+        let workspace = self.state.workspace();
+        let imported = self.state.folder_manager.import_from_json(json, &workspace)?;
+        let folders: Vec<Value> = imported.iter().map(|id| self.folder_data(id)).collect();
+        Ok(Response::new(format!("Imported {} folders", imported.len()), json!({ "folders": folders })))
+    }
+
+    fn add_file_to_folder(&mut self, folder_id: String, file_path: String) -> Result<Response, Error> {
+        let folder_id = self.state.folder_manager.resolve_folder_key(&folder_id)?;
+        let file_path = self.state.workspace().normalize(&file_path);
+        self.state.folder_manager.add_file_to_folder(&folder_id, file_path.clone())?;
+        Ok(Response::new(
+            format!("File '{}' added to folder '{}'", file_path, self.folder_name(&folder_id)),
+            json!({ "folder_id": folder_id, "path": file_path }),
+        ))
+    }
+
+    fn remove_file_from_folder(&mut self, folder_id: String, file_path: String) -> Result<Response, Error> {
+        let folder_id = self.state.folder_manager.resolve_folder_key(&folder_id)?;
+        let file_path = self.state.workspace().normalize(&file_path);
+        self.state.folder_manager.remove_file_from_folder(&folder_id, &file_path)?;
+        Ok(Response::new(
+            format!("File '{}' removed from folder '{}'", file_path, self.folder_name(&folder_id)),
+            json!({ "folder_id": folder_id, "path": file_path }),
+        ))
+    }
+
+    fn add_pattern_to_folder(&mut self, folder_id: String, pattern: String) -> Result<Response, Error> {
+        let folder_id = self.state.folder_manager.resolve_folder_key(&folder_id)?;
+        self.state.folder_manager.add_pattern_to_folder(&folder_id, pattern.clone())?;
+        Ok(Response::new(
+            format!("Pattern '{}' added to folder '{}'", pattern, self.folder_name(&folder_id)),
+            json!({ "folder_id": folder_id, "pattern": pattern }),
+        ))
+    }
+
+    fn remove_pattern_from_folder(&mut self, folder_id: String, pattern: String) -> Result<Response, Error> {
+        let folder_id = self.state.folder_manager.resolve_folder_key(&folder_id)?;
+        self.state.folder_manager.remove_pattern_from_folder(&folder_id, &pattern)?;
+        Ok(Response::new(
+            format!("Pattern '{}' removed from folder '{}'", pattern, self.folder_name(&folder_id)),
+            json!({ "folder_id": folder_id, "pattern": pattern }),
+        ))
+    }
+
+    // Only called with ids the manager has just resolved or returned
+    fn folder_name(&self, folder_id: &str) -> String {
+        self.state
+            .folder_manager
+            .get_folder(folder_id)
+            .map(|f| f.name.clone())
+            .unwrap_or_else(|| folder_id.to_string())
+    }
+
+    fn folder_data(&self, folder_id: &str) -> Value {
+        self.state
+            .folder_manager
+            .get_folder(folder_id)
+            .map(folder_summary)
+            .unwrap_or(Value::Null)
+    }
+
+    // Stored paths are relative to the root, so they move with it
+    fn set_workspace_root(&mut self, root: &str) -> Response {
+        let previous = self.state.workspace();
+        if root == "off" {
+            self.state.workspace_root = None;
+            self.state.rebase_paths(&previous);
+            return Response::new("Workspace root reset to the current directory", json!({ "root": null }));
+        }
+        self.state.workspace_root = Some(root.to_string());
+        self.state.rebase_paths(&previous);
+        Response::new(format!("Workspace root set to '{}'", root), json!({ "root": root }))
+    }
+
+    fn set_path_display(&mut self, mode: DisplayMode) -> Response {
+        self.state.path_display = mode;
+        let message = format!("Paths are shown {}", match mode {
+            DisplayMode::Relative => "relative to the workspace root",
+            DisplayMode::Absolute => "as absolute paths",
+            DisplayMode::Project => "prefixed with the project name",
+        });
+        Response::new(message, json!({ "mode": mode.name() }))
+    }
+
+    fn set_resolve_symlinks(&mut self, enabled: bool) -> Response {
+        self.state.resolve_symlinks = enabled;
+        let message = if enabled {
+            "Symlinks are resolved when paths are normalized"
+        } else {
+            "Symlinks are kept as given"
+        };
+        Response::new(message, json!({ "enabled": enabled }))
+    }
+
+    // Every path that enters the state is stored relative to the workspace root
+    fn normalize_paths(&self, paths: Vec<String>) -> Vec<String> {
+        let workspace = self.state.workspace();
+        paths.iter().map(|path| workspace.normalize(path)).collect()
+    }
+
+    fn copy_folder_contents(&mut self, folder_id: String, recursive: bool, formatter: &dyn OutputFormatter) -> Result<Response, Error> {
+        let folder_id = self.state.folder_manager.resolve_folder_key(&folder_id)?;
+        let workspace = self.state.workspace();
+        let combined_content = self.state.folder_manager.copy_folder_contents(&folder_id, &workspace, recursive, formatter)?;
+
+        // In a real implementation, this would be copied to the system clipboard
+        Ok(Response::new(
+            "Copied folder contents to clipboard",
+            json!({
+                "folder_id": folder_id,
+                "recursive": recursive,
+                "tokens": self.state.token_estimator.count(&combined_content),
+                "content": combined_content,
+            }),
+        ))
+    }
+
+    fn set_output_format(&mut self, format: OutputFormat) -> Response {
+        self.state.output_format = format;
+        Response::new(format!("Output format set to {}", format.name()), json!({ "format": format.name() }))
+    }
+
+    fn set_response_format(&mut self, format: ResponseFormat) -> Response {
+        self.state.response_format = format;
+        Response::new(format!("Responses are written as {}", format.name()), json!({ "format": format.name() }))
+    }
+
+    fn define_template(&mut self, template: OutputTemplate) -> Result<Response, Error> {
+        let name = template.name.clone();
+        let replaced = self.state.templates.define(template)?;
+        let message = if replaced {
+            format!("Template '{}' updated", name)
+        } else {
+            format!("Template '{}' defined", name)
+        };
+        Ok(Response::new(message, json!({ "name": name, "replaced": replaced })))
+    }
+
+    fn delete_template(&mut self, name: &str) -> Result<Response, Error> {
+        self.state.templates.remove(name)?;
+        Ok(Response::new(format!("Template '{}' deleted", name), json!({ "name": name })))
+    }
+
+    fn switch_buffer(&mut self, name: &str) -> Result<Response, Error> {
+        let created = self.state.clipboard.switch_buffer(name)?;
+        let files = self.state.clipboard.active().len();
+        let message = if created {
+            format!("Switched to new buffer '{}'", name)
+        } else {
+            format!("Switched to buffer '{}' ({} files)", name, files)
+        };
+        Ok(Response::new(message, json!({ "buffer": name, "created": created, "files": files })))
+    }
+
+    fn merge_buffer(&mut self, source: &str, target: &str) -> Result<Response, Error> {
+        let len = self.state.clipboard.merge_buffer(source, target)?;
+        Ok(Response::new(
+            format!("Merged buffer '{}' into '{}' ({} files)", source, target, len),
+            json!({ "source": source, "target": target, "files": len }),
+        ))
+    }
+
+    fn clear_buffer(&mut self, name: &str) -> Result<Response, Error> {
+        self.state.clipboard.clear_buffer(name)?;
+        Ok(Response::new(format!("Buffer '{}' cleared", name), json!({ "buffer": name })))
+    }
+
+    fn undo_clipboard(&mut self) -> Result<Response, Error> {
+        let buffer = self.state.clipboard.active_mut();
+        let label = buffer.undo()?;
+        Ok(Response::new(
+            format!("Undid {} ({} files)", label, buffer.len()),
+            json!({ "label": label, "files": buffer.len() }),
+        ))
+    }
+
+    fn redo_clipboard(&mut self) -> Result<Response, Error> {
+        let buffer = self.state.clipboard.active_mut();
+        let label = buffer.redo()?;
+        Ok(Response::new(
+            format!("Redid {} ({} files)", label, buffer.len()),
+            json!({ "label": label, "files": buffer.len() }),
+        ))
+    }
+
+    fn restore_clipboard(&mut self, index: usize) -> Result<Response, Error> {
+        let buffer = self.state.clipboard.active_mut();
+        buffer.restore_snapshot(index)?;
+        Ok(Response::new(
+            format!("Restored history entry {} ({} files)", index, buffer.len()),
+            json!({ "index": index, "files": buffer.len() }),
+        ))
+    }
+
+    fn list_templates(&self) -> Response {
+        let names = self.state.templates.list();
+        let message = if names.is_empty() {
+            "No templates defined".to_string()
+        } else {
+            names.join("\n")
+        };
+        Response::new(message, json!({ "templates": names }))
+    }
+
+    // A named template takes precedence over the output format
+    fn resolve_formatter(&self, template: Option<&str>, format: Option<OutputFormat>) -> Result<Box<dyn OutputFormatter>, Error> {
+        let formatter: Box<dyn OutputFormatter> = match template {
+            Some(name) => Box::new(self.state.templates.get(name)?.clone()),
+            None => format.unwrap_or(self.state.output_format).formatter(),
+        };
+        Ok(self.state.display_formatter(formatter))
+    }
+
+    fn list_folders(&self) -> Response {
+        let mut lines = Vec::new();
+        let mut folders = Vec::new();
+        let workspace = self.state.workspace();
+        for folder in self.state.folder_manager.list_folders() {
+            let mut data = folder_summary(folder);
+            if folder.patterns.is_empty() {
+                lines.push(format!("{}: {} ({} files)", folder.id, folder.name, folder.file_count()));
+                folders.push(data);
+                continue;
             }
-            "create_folder" => {
-                if !args.is_empty() {
-                    let name = args[0].clone();
-                    let initial_files = if args.len() > 1 { args[1..].to_vec() } else { vec![] };
-                    self.create_folder(name, initial_files)
-                } else {
-                    Err("Missing argument: folder name required".to_string())
+
+            // Pattern folders show what their patterns currently match
+            match self.state.folder_manager.resolve_folder_files(&folder.id, &workspace) {
+                Ok(files) => {
+                    lines.push(format!(
+                        "{}: {} ({} files, patterns: {})",
+                        folder.id,
+                        folder.name,
+                        files.len(),
+                        folder.patterns.join(" ")
+                    ));
+                    lines.extend(files.iter().map(|file| format!("  {}", file)));
+                    data["matched"] = json!(files);
                 }
-            }
-            "delete_folder" => {
-                if !args.is_empty() {
-                    self.delete_folder(args[0].clone())
-                } else {
-                    Err("Missing argument: folder_id required".to_string())
+                Err(error) => {
+                    lines.push(format!(
+                        "{}: {} (patterns: {}; {})",
+                        folder.id,
+                        folder.name,
+                        folder.patterns.join(" "),
+                        error
+                    ));
+                    data["error"] = response::error_data(&error);
                 }
             }
-            "rename_folder" => {
-                if args.len() >= 2 {
-                    self.rename_folder(args[0].clone(), args[1].clone())
-                } else {
-                    Err("Missing arguments: folder_id and new_name required".to_string())
+            folders.push(data);
+        }
+        Response::new(lines.join("\n"), json!({ "folders": folders }))
+    }
+
+    fn get_status(&self) -> Response {
+        let clipboard = &self.state.clipboard;
+        let tokens = if clipboard.has_files() { self.state.clipboard_tokens() } else { 0 };
+        Response::new(
+            self.state.status_message(),
+            json!({
+                "buffer": clipboard.active_buffer,
+                "files": clipboard.get_file_count(),
+                "tokens": tokens,
+                "budget": budget_data(self.state.token_budget.as_ref()),
+                "folders": self.state.folder_manager.get_folder_count(),
+                "folder_files": self.state.folder_manager.get_total_files_count(),
+            }),
+        )
+    }
+
+    // Runs a command and renders its result in the response format, so
+    // failures are reported the same way as results
+    fn run_command(&mut self, command: &str, args: Vec<String>) -> String {
+        let result = self.execute(command, args);
+        if let Err(error) = &result {
+            eprintln!("Extension error [{}]: {}", error.code(), error);
+        }
+        self.state.response_format.render(&result)
+    }
+
+    fn execute(&mut self, command: &str, args: Vec<String>) -> Result<Response, Error> {
+        let command = Command::parse(command, args)?;
+        let mutating = command.is_mutating();
+        let result = self.handle_command(command);
+
+        match result {
+            // The command itself succeeded, so an unsaved change is a warning
+            Ok(response) if mutating => match self.save_state() {
+                Ok(()) => Ok(response),
+                Err(error) => {
+                    eprintln!("Extension error: failed to save state: {}", error);
+                    Ok(response.with_warning(format!("Changes were not saved: {}", error)))
                 }
-            }
-            "add_file_to_folder" => {
-                if args.len() >= 2 {
-                    self.add_file_to_folder(args[0].clone(), args[1].clone())
-                } else {
-                    Err("Missing arguments: folder_id and file_path required".to_string())
+            },
+            result => result,
+        }
+    }
+
+    // Runs every step against the same state and reports each one. A failed
+    // step fails the batch's data but not the batch itself; with
+    // `transactional` the state is put back as it was before the first step.
+    fn run_batch(&mut self, steps: &[BatchStep], mode: BatchMode, transactional: bool) -> Response {
+        let before = transactional.then(|| self.state.clone());
+        let mut lines = Vec::new();
+        let mut results = Vec::new();
+        let mut failed = 0;
+
+        for (index, step) in steps.iter().enumerate() {
+            let result = step.parse().and_then(|command| self.handle_command(command));
+            let text = ResponseFormat::Text.render(&result).replace('\n', "\n   ");
+            lines.push(format!("{}. {}: {}", index + 1, step.command, text));
+
+            let mut data = response::envelope(&result);
+            data["step"] = json!(index + 1);
+            data["command"] = json!(step.command);
+            results.push(data);
+
+            if result.is_err() {
+                failed += 1;
+                if mode == BatchMode::Stop {
+                    break;
                 }
             }
-            "remove_file_from_folder" => {
-                if args.len() >= 2 {
-                    self.remove_file_from_folder(args[0].clone(), args[1].clone())
-                } else {
-                    Err("Missing arguments: folder_id and file_path required".to_string())
-                }
+        }
+
+        let rolled_back = match before {
+            Some(before) if failed > 0 => {
+                self.state = before;
+                true
             }
-            "copy_folder_contents" => {
-                if !args.is_empty() {
-                    self.copy_folder_contents(args[0].clone())
-                } else {
-                    Err("Missing argument: folder_id required".to_string())
-                }
+            _ => false,
+        };
+
+        lines.push(format!("Ran {} of {} steps, {} failed", results.len(), steps.len(), failed));
+        if rolled_back {
+            lines.push("All changes were rolled back".to_string());
+        }
+        let response = Response::new(
+            lines.join("\n"),
+            json!({
+                "steps": results,
+                "completed": results.len() - failed,
+                "failed": failed,
+                "skipped": steps.len() - results.len(),
+                "mode": mode.name(),
+                "rolled_back": rolled_back,
+            }),
+        );
+        if failed > 0 {
+            response.with_warning(format!("{} of {} steps failed", failed, steps.len()))
+        } else {
+            response
+        }
+    }
+
+    fn handle_command(&mut self, command: Command) -> Result<Response, Error> {
+        match command {
+            Command::CopyPathWithContent { file_path, content, start_line, end_line, selected_content, format, template, buffer } => {
+                let selection = match (start_line, end_line) {
+                    (Some(start_line), Some(end_line)) => {
+                        let selected_content = selected_content.unwrap_or_else(|| content.clone());
+                        Some(FileSelection::new(start_line, end_line, selected_content))
+                    }
+                    _ => None,
+                };
+                let formatter = self.resolve_formatter(template.as_deref(), format)?;
+                self.copy_current_file(file_path, content, selection, buffer.as_deref(), formatter.as_ref())
+            }
+            Command::CopyDirectory { path, include, exclude, max_size, format, template, buffer } => {
+                let options = DirectoryOptions {
+                    include,
+                    exclude,
+                    max_file_size: max_size.unwrap_or(directory::DEFAULT_MAX_FILE_SIZE),
+                };
+                let formatter = self.resolve_formatter(template.as_deref(), format)?;
+                self.copy_directory(&path, &options, buffer.as_deref(), formatter.as_ref())
             }
-            "list_folders" => {
-                let folders = self.list_folders();
-                Ok(folders.join("\n"))
+            Command::ClearClipboard => Ok(self.clear_clipboard()),
+            Command::ListClipboard => Ok(self.list_clipboard()),
+            Command::SetTokenBudget { limit, mode } => Ok(self.set_token_budget(limit, mode)),
+            Command::SetTokenEstimator { estimator } => Ok(self.set_token_estimator(estimator)),
+            Command::FitToBudget { limit, unit, strategy, format, template } => {
+                let formatter = self.resolve_formatter(template.as_deref(), format)?;
+                self.fit_to_budget(limit, unit, strategy, formatter.as_ref())
             }
-            "status" => {
-                Ok(self.get_status())
+            Command::ClipboardStatus => Ok(self.clipboard_status()),
+            Command::RefreshClipboard => Ok(self.refresh_clipboard()),
+            Command::TokenReport => Ok(self.token_report()),
+            Command::SwitchBuffer { name } => self.switch_buffer(&name),
+            Command::ListBuffers => Ok(self.list_buffers()),
+            Command::MergeBuffer { source, target } => self.merge_buffer(&source, &target),
+            Command::ClearBuffer { name } => self.clear_buffer(&name),
+            Command::UndoClipboard => self.undo_clipboard(),
+            Command::RedoClipboard => self.redo_clipboard(),
+            Command::ClipboardHistory => Ok(self.clipboard_history()),
+            Command::RestoreClipboard { index } => self.restore_clipboard(index),
+            Command::MoveClipboardEntry { entry, position } => self.move_clipboard_entry(&entry, position),
+            Command::MoveClipboardEntryUp { entry } => self.move_clipboard_entry_up(&entry),
+            Command::MoveClipboardEntryDown { entry } => self.move_clipboard_entry_down(&entry),
+            Command::SortClipboard { key } => Ok(self.sort_clipboard(key)),
+            Command::ReverseClipboard => Ok(self.reverse_clipboard()),
+            Command::SetPreservePosition { enabled } => Ok(self.set_preserve_position(enabled)),
+            Command::CreateFolder { name, files } => self.create_folder(name, files),
+            Command::CreateSubfolder { parent, name, files } => self.create_subfolder(parent, name, files),
+            Command::MoveFolder { folder, parent } => self.move_folder(folder, &parent),
+            Command::FolderTree => Ok(self.folder_tree()),
+            Command::DeleteFolder { folder, recursive } => self.delete_folder(folder, recursive),
+            Command::RenameFolder { folder, new_name } => self.rename_folder(folder, new_name),
+            Command::CloneFolder { folder, new_name } => self.clone_folder(folder, new_name),
+            Command::ImportFolders { folders } => self.import_folders(&folders.to_string()),
+            Command::AddFileToFolder { folder, file_path } => self.add_file_to_folder(folder, file_path),
+            Command::RemoveFileFromFolder { folder, file_path } => self.remove_file_from_folder(folder, file_path),
+            Command::AddPatternToFolder { folder, pattern } => self.add_pattern_to_folder(folder, pattern),
+            Command::RemovePatternFromFolder { folder, pattern } => self.remove_pattern_from_folder(folder, pattern),
+            Command::SetWorkspaceRoot { root } => Ok(self.set_workspace_root(&root)),
+            Command::SetPathDisplay { mode } => Ok(self.set_path_display(mode)),
+            Command::SetResolveSymlinks { enabled } => Ok(self.set_resolve_symlinks(enabled)),
+            Command::CopyFolderContents { folder, recursive, format, template } => {
+                let formatter = self.resolve_formatter(template.as_deref(), format)?;
+                self.copy_folder_contents(folder, recursive, formatter.as_ref())
             }
-            _ => {
-                Err(format!("Unknown command: {}", command))
+            Command::SetOutputFormat { format } => Ok(self.set_output_format(format)),
+            Command::SetResponseFormat { format } => Ok(self.set_response_format(format)),
+            Command::DefineTemplate(template) => self.define_template(template),
+            Command::DeleteTemplate { name } => self.delete_template(&name),
+            Command::ListTemplates => Ok(self.list_templates()),
+            Command::ListFolders => Ok(self.list_folders()),
+            Command::Status => Ok(self.get_status()),
+            Command::Help { command: None } => Ok(list_commands()),
+            Command::Help { command: Some(name) } => {
+                let help = commands::help(&name).ok_or_else(|| commands::unknown_command(&name))?;
+                Ok(describe_command(help))
             }
+            Command::Batch { steps, mode, transactional } => Ok(self.run_batch(&steps, mode.unwrap_or_default(), transactional)),
         }
     }
 }
 
+fn list_commands() -> Response {
+    let mut lines = Vec::new();
+    for help in commands::COMMANDS {
+        lines.push(help.usage());
+        lines.push(format!("    {}", help.summary));
+    }
+    lines.push("Run 'help <command>' for its arguments and examples.".to_string());
+
+    let commands: Vec<Value> = commands::COMMANDS
+        .iter()
+        .map(|help| json!({ "name": help.name, "summary": help.summary, "usage": help.usage() }))
+        .collect();
+    Response::new(lines.join("\n"), json!({ "commands": commands }))
+}
+
+fn describe_command(help: &CommandHelp) -> Response {
+    let mut lines = vec![help.usage(), String::new(), help.summary.to_string()];
+    if !help.arguments.is_empty() {
+        let width = help.arguments.iter().map(|(argument, _)| argument.len()).max().unwrap_or(0);
+        lines.push(String::new());
+        lines.push("Arguments:".to_string());
+        lines.extend(
+            help.arguments
+                .iter()
+                .map(|(argument, description)| format!("  {:width$}  {}", argument, description, width = width)),
+        );
+    }
+    lines.push(String::new());
+    lines.push("Examples:".to_string());
+    lines.extend(help.examples.iter().map(|example| format!("  {}", example)));
+
+    let arguments: Vec<Value> = help
+        .arguments
+        .iter()
+        .map(|(argument, description)| json!({ "argument": argument, "description": description }))
+        .collect();
+    Response::new(
+        lines.join("\n"),
+        json!({
+            "command": {
+                "name": help.name,
+                "summary": help.summary,
+                "usage": help.usage(),
+                "arguments": arguments,
+                "examples": help.examples,
+            }
+        }),
+    )
+}
+
+fn moved_response(moved: MovedEntry) -> Response {
+    Response::new(
+        format!("Moved '{}' to position {}", moved.path, moved.position),
+        json!({ "path": moved.path, "position": moved.position }),
+    )
+}
+
+// `position` is 1-based, as shown by list_clipboard
+fn entry_data(index: usize, file: &CopiedFile) -> Value {
+    let lines: Vec<Value> = file
+        .selections
+        .iter()
+        .map(|selection| json!({ "start": selection.start_line, "end": selection.end_line }))
+        .collect();
+    json!({
+        "position": index + 1,
+        "path": file.base_path,
+        "display_path": file.display_path,
+        "lines": lines,
+        "copied_at": file.copied_at.to_rfc3339(),
+    })
+}
+
+fn folder_summary(folder: &Folder) -> Value {
+    json!({
+        "id": folder.id,
+        "name": folder.name,
+        "parent_id": folder.parent_id,
+        "files": folder.files,
+        "patterns": folder.patterns,
+        "color": folder.color,
+    })
+}
+
+fn budget_data(budget: Option<&TokenBudget>) -> Value {
+    match budget {
+        Some(budget) => json!({ "limit": budget.limit, "mode": budget.mode.name() }),
+        None => Value::Null,
+    }
+}
+
 impl zed::Extension for CopyPathWithCodeExtension {
     fn new() -> Self {
         Self::new()
@@ -169,13 +1070,7 @@ impl zed::Extension for CopyPathWithCodeExtension {
         command: &str,
         args: Vec<String>,
     ) -> zed::Result<Option<String>> {
-        match self.handle_command(command, args) {
-            Ok(result) => Ok(Some(result)),
-            Err(error) => {
-                eprintln!("Extension error: {}", error);
-                Ok(Some(format!("Error: {}", error)))
-            }
-        }
+        Ok(Some(self.run_command(command, args)))
     }
 
     fn language_server_initialization_options(
@@ -194,3 +1089,45 @@ impl zed::Extension for CopyPathWithCodeExtension {
 }
 
 zed::register_extension!(CopyPathWithCodeExtension);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn state_path(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("copy-path-with-code-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir.join(storage::STATE_FILE_NAME)
+    }
+
+    #[test]
+    fn test_fit_to_budget_names_its_strategies() {
+        let mut extension = CopyPathWithCodeExtension::with_storage(Storage::new(state_path("fit")));
+        let content = "x\n".repeat(200);
+        extension.execute("copy_path_with_content", vec!["a.txt".to_string(), content]).unwrap();
+
+        let args = vec!["100".to_string(), "--unit=bytes".to_string(), "--strategy=head-tail,drop-oldest".to_string()];
+        let response = extension.execute("fit_to_budget", args).unwrap();
+        assert_eq!(response.data["strategies"], json!(["head-tail", "drop-oldest"]));
+        assert_eq!(response.data["fits"], true);
+    }
+
+    #[test]
+    fn test_unsaved_changes_are_reported() {
+        let path = state_path("unsaved");
+        let newer = r#"{"version": 999, "state": {}}"#;
+        fs::write(&path, newer).unwrap();
+
+        let mut extension = CopyPathWithCodeExtension::with_storage(Storage::new(path.clone()));
+        let response = extension.execute("create_folder", vec!["Docs".to_string()]).unwrap();
+        assert_eq!(response.warnings.len(), 1);
+        assert!(response.warnings[0].starts_with("Changes were not saved: "), "{}", response.warnings[0]);
+        assert_eq!(fs::read_to_string(&path).unwrap(), newer);
+
+        // Commands that change nothing do not try to save
+        let response = extension.execute("status", vec![]).unwrap();
+        assert!(response.warnings.is_empty());
+    }
+}
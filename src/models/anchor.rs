@@ -0,0 +1,162 @@
+use super::FileSelection;
+use std::collections::HashMap;
+
+// Below this similarity a relocated range is not trusted
+pub const MIN_CONFIDENCE: f64 = 0.7;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Relocation {
+    pub start_line: u32,
+    pub end_line: u32,
+    // 1.0 for an exact match of the stored text
+    pub confidence: f64,
+}
+
+impl Relocation {
+    pub fn is_confident(&self) -> bool {
+        self.confidence >= MIN_CONFIDENCE
+    }
+
+    pub fn format_lines(&self) -> String {
+        FileSelection::new(self.start_line, self.end_line, String::new()).format_lines()
+    }
+}
+
+// Finds where the selection's stored text now lives in `lines`: the exact text
+// closest to the old location, otherwise the most similar range, preferring
+// ranges near the old location
+pub fn relocate(selection: &FileSelection, lines: &[&str]) -> Option<Relocation> {
+    let anchor: Vec<&str> = selection.content.split('\n').collect();
+    if lines.is_empty() {
+        return None;
+    }
+
+    let old_start = selection.start_line.saturating_sub(1) as usize;
+    let distance = |start: usize| start.abs_diff(old_start);
+
+    let exact = (0..=lines.len().saturating_sub(anchor.len()))
+        .filter(|&start| start + anchor.len() <= lines.len())
+        .filter(|&start| lines[start..start + anchor.len()] == anchor[..])
+        .min_by_key(|&start| distance(start));
+    if let Some(start) = exact {
+        return Some(to_relocation(start, start + anchor.len(), 1.0));
+    }
+
+    // Best window of the same length, with a small penalty for moving far away
+    let window = anchor.len().min(lines.len());
+    let penalty = |start: usize| 0.1 * distance(start) as f64 / lines.len() as f64;
+    let (best_start, _) = (0..=lines.len() - window)
+        .map(|start| (start, similarity(&anchor, &lines[start..start + window]) - penalty(start)))
+        .max_by(|a, b| a.1.total_cmp(&b.1).then_with(|| distance(b.0).cmp(&distance(a.0))))?;
+
+    // Lines inserted or removed inside the range shift its end; snap both ends
+    // to the nearest lines matching the stored first and last lines
+    let slack = anchor.len() / 2 + 1;
+    let start = snap(lines, anchor[0], best_start, slack, 0..best_start + window);
+    let end = snap(lines, anchor[anchor.len() - 1], best_start + window - 1, slack, start..lines.len());
+
+    let confidence = similarity(&anchor, &lines[start..=end]);
+    Some(to_relocation(start, end + 1, confidence))
+}
+
+fn to_relocation(start: usize, end: usize, confidence: f64) -> Relocation {
+    Relocation {
+        start_line: start as u32 + 1,
+        end_line: end as u32,
+        confidence,
+    }
+}
+
+// The line within `slack` of `around` (and inside `bounds`) equal to `target`
+// ignoring indentation, or `around` itself
+fn snap(lines: &[&str], target: &str, around: usize, slack: usize, bounds: std::ops::Range<usize>) -> usize {
+    let target = target.trim();
+    if target.is_empty() {
+        return around.clamp(bounds.start, bounds.end.saturating_sub(1));
+    }
+
+    (0..=slack)
+        .flat_map(|offset| [around.checked_sub(offset), around.checked_add(offset)])
+        .flatten()
+        .filter(|index| bounds.contains(index))
+        .find(|&index| lines[index].trim() == target)
+        .unwrap_or_else(|| around.clamp(bounds.start, bounds.end.saturating_sub(1)))
+}
+
+// Dice coefficient over the multisets of trimmed, non-blank lines
+fn similarity(a: &[&str], b: &[&str]) -> f64 {
+    let mut counts: HashMap<&str, isize> = HashMap::new();
+    let mut total = 0;
+    for line in a.iter().map(|line| line.trim()).filter(|line| !line.is_empty()) {
+        *counts.entry(line).or_default() += 1;
+        total += 1;
+    }
+
+    let mut common = 0;
+    for line in b.iter().map(|line| line.trim()).filter(|line| !line.is_empty()) {
+        total += 1;
+        if let Some(count) = counts.get_mut(line).filter(|count| **count > 0) {
+            *count -= 1;
+            common += 1;
+        }
+    }
+
+    if total == 0 {
+        // Both sides are blank
+        return 1.0;
+    }
+    2.0 * common as f64 / total as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(text: &str) -> Vec<&str> {
+        text.split('\n').collect()
+    }
+
+    fn selection(start_line: u32, end_line: u32, content: &str) -> FileSelection {
+        FileSelection::new(start_line, end_line, content.to_string())
+    }
+
+    const SOURCE: &str = "use a;\n\nfn one() {\n    1\n}\n\nfn two() {\n    2\n}";
+
+    #[test]
+    fn test_exact_match_after_insertion_above() {
+        let moved = format!("use b;\nuse c;\n{}", SOURCE);
+        let relocation = relocate(&selection(7, 9, "fn two() {\n    2\n}"), &lines(&moved)).unwrap();
+
+        assert_eq!((relocation.start_line, relocation.end_line), (9, 11));
+        assert_eq!(relocation.confidence, 1.0);
+    }
+
+    #[test]
+    fn test_exact_match_prefers_nearest_occurrence() {
+        let source = "x\n}\nx\n}\nx\n}";
+        let relocation = relocate(&selection(3, 4, "x\n}"), &lines(source)).unwrap();
+        assert_eq!(relocation.start_line, 3);
+    }
+
+    #[test]
+    fn test_fuzzy_match_with_edited_and_inserted_lines() {
+        let stored = "fn sum() {\n    let a = 1;\n    let b = 2;\n    a + b\n}";
+        let edited = format!(
+            "// new header\n{}\n\nfn sum() {{\n    let a = 1;\n    let extra = 0;\n    let b = 3;\n    a + b\n}}",
+            SOURCE
+        );
+
+        let relocation = relocate(&selection(11, 15, stored), &lines(&edited)).unwrap();
+        assert_eq!((relocation.start_line, relocation.end_line), (12, 17));
+        assert!(relocation.is_confident(), "confidence {}", relocation.confidence);
+    }
+
+    #[test]
+    fn test_rewritten_range_is_not_confident() {
+        let stored = "fn gone() {\n    old();\n    older();\n}";
+        let relocation = relocate(&selection(3, 6, stored), &lines(SOURCE)).unwrap();
+        assert!(!relocation.is_confident());
+
+        assert!(relocate(&selection(1, 1, "x"), &[]).is_none());
+    }
+}
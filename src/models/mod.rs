@@ -1,10 +1,26 @@
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+mod anchor;
+
+pub use anchor::Relocation;
+
+pub const ELISION_MARKER: &str = "…";
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CopiedFile {
     pub display_path: String,
     pub base_path: String,
     pub content: String,
+    // Sorted, non-overlapping line ranges; empty when the whole file was copied
+    #[serde(default)]
+    pub selections: Vec<FileSelection>,
+    pub copied_at: DateTime<Utc>,
+    // Hash of `content` as captured, before any trimming; None for entries saved
+    // by versions that did not record it
+    #[serde(default)]
+    pub content_hash: Option<String>,
 }
 
 impl CopiedFile {
@@ -12,17 +28,183 @@ impl CopiedFile {
         Self {
             display_path,
             base_path,
+            content_hash: Some(content_hash(&content)),
             content,
+            selections: Vec::new(),
+            copied_at: Utc::now(),
+        }
+    }
+
+    pub fn from_selection(base_path: String, selection: FileSelection) -> Self {
+        let mut copied_file = Self::new(base_path.clone(), base_path, String::new());
+        copied_file.selections.push(selection);
+        copied_file.update_rendered();
+        copied_file
+    }
+
+    pub fn is_partial(&self) -> bool {
+        !self.selections.is_empty()
+    }
+
+    // What `display_path` adds to the base path, such as `:10-12`; empty when
+    // it does not start with the base path, as in a hand-edited state file
+    pub fn display_suffix(&self) -> &str {
+        self.display_path.strip_prefix(self.base_path.as_str()).unwrap_or("")
+    }
+
+    // Moves the entry to another stored path; the line ranges of a partial
+    // copy stay as they are
+    pub fn rebase(&mut self, base_path: String) {
+        self.display_path = format!("{}{}", base_path, self.display_suffix());
+        self.base_path = base_path;
+    }
+
+    pub fn captured_hash(&self) -> String {
+        self.content_hash
+            .clone()
+            .unwrap_or_else(|| content_hash(&self.content))
+    }
+
+    // Rebuilds the entry from the current text of the file. The ranges of a
+    // partial copy follow their stored text, so lines inserted above or edits
+    // inside a range do not lose it; a range that cannot be found confidently
+    // is an error.
+    pub fn recapture(&self, source: &str) -> Result<CopiedFile, String> {
+        if !self.is_partial() {
+            return Ok(CopiedFile::new(
+                self.display_path.clone(),
+                self.base_path.clone(),
+                source.to_string(),
+            ));
+        }
+
+        let lines: Vec<&str> = source.lines().collect();
+        let mut recaptured = self.clone();
+        for selection in &mut recaptured.selections {
+            let relocation = selection.relocate(&lines).ok_or("the file is empty")?;
+            if !relocation.is_confident() {
+                return Err(format!(
+                    "lines {} could not be found (best match {}% similar at {})",
+                    selection.format_lines(),
+                    (relocation.confidence * 100.0).round(),
+                    relocation.format_lines()
+                ));
+            }
+
+            let (start, end) = (relocation.start_line as usize, relocation.end_line as usize);
+            selection.start_line = relocation.start_line;
+            selection.end_line = relocation.end_line;
+            selection.content = lines[start - 1..end].join("\n");
+            selection.trimmed = None;
+        }
+
+        // Relocated ranges can meet or overlap, which a copy would have merged
+        let mut selections = std::mem::take(&mut recaptured.selections).into_iter();
+        if let Some(first) = selections.next() {
+            recaptured.selections.push(first);
+            for selection in selections {
+                recaptured.add_selection(selection);
+            }
+        }
+
+        recaptured.copied_at = Utc::now();
+        recaptured.update_rendered();
+        Ok(recaptured)
+    }
+
+    pub fn add_selection(&mut self, selection: FileSelection) {
+        let mut merged = selection;
+        let mut kept = Vec::with_capacity(self.selections.len() + 1);
+
+        // Existing selections never touch each other, so anything touching the
+        // merged range must touch the new selection or one already absorbed
+        for existing in self.selections.drain(..) {
+            if existing.touches(&merged) {
+                merged = existing.merge(merged);
+            } else {
+                kept.push(existing);
+            }
+        }
+
+        kept.push(merged);
+        kept.sort_by_key(|s| s.start_line);
+        self.selections = kept;
+        self.copied_at = Utc::now();
+        self.update_rendered();
+    }
+
+    // Shortens the entry's text; a partial entry is rewritten one selection at
+    // a time, so later copies into it keep the cut. Returns whether anything changed.
+    pub fn trim_with(&mut self, mut rewrite: impl FnMut(&str) -> Option<String>) -> bool {
+        if !self.is_partial() {
+            return match rewrite(&self.content) {
+                Some(content) => {
+                    self.content = content;
+                    true
+                }
+                None => false,
+            };
+        }
+
+        let mut changed = false;
+        for selection in &mut self.selections {
+            if let Some(trimmed) = rewrite(selection.rendered()) {
+                selection.trimmed = Some(trimmed);
+                changed = true;
+            }
+        }
+        if changed {
+            self.update_rendered();
+        }
+        changed
+    }
+
+    fn update_rendered(&mut self) {
+        if self.selections.is_empty() {
+            return;
         }
+
+        let ranges = self
+            .selections
+            .iter()
+            .map(|s| s.format_lines())
+            .collect::<Vec<_>>()
+            .join(",");
+        self.display_path = format!("{}:{}", self.base_path, ranges);
+
+        let separator = format!("\n{}\n", ELISION_MARKER);
+        let join = |text: fn(&FileSelection) -> &str| {
+            self.selections.iter().map(text).collect::<Vec<_>>().join(&separator)
+        };
+        // The hash is of the text as captured, like that of a whole-file entry
+        self.content_hash = Some(content_hash(&join(|s| s.content.as_str())));
+        self.content = join(FileSelection::rendered);
     }
 }
 
+// 64-bit FNV-1a as hex; stable across builds, unlike the std hashers
+pub fn content_hash(text: &str) -> String {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in text.bytes() {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    format!("{:016x}", hash)
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Folder {
     pub id: String,
     pub name: String,
     pub files: Vec<String>,
     pub color: Option<String>,
+    // Globs resolved against the workspace root whenever the folder is used;
+    // a leading `!` excludes matches
+    #[serde(default)]
+    pub patterns: Vec<String>,
+    // None for top-level folders
+    #[serde(default)]
+    pub parent_id: Option<String>,
 }
 
 impl Folder {
@@ -32,6 +214,8 @@ impl Folder {
             name,
             files: Vec::new(),
             color: None,
+            patterns: Vec::new(),
+            parent_id: None,
         }
     }
 
@@ -60,97 +244,198 @@ impl Folder {
     pub fn file_count(&self) -> usize {
         self.files.len()
     }
+
+    pub fn add_pattern(&mut self, pattern: String) -> bool {
+        if !self.patterns.contains(&pattern) {
+            self.patterns.push(pattern);
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn remove_pattern(&mut self, pattern: &str) -> bool {
+        let initial_len = self.patterns.len();
+        self.patterns.retain(|p| p != pattern);
+        self.patterns.len() != initial_len
+    }
+
+    // (include globs, exclude globs without their `!`)
+    pub fn split_patterns(&self) -> (Vec<String>, Vec<String>) {
+        let mut include = Vec::new();
+        let mut exclude = Vec::new();
+        for pattern in &self.patterns {
+            match pattern.strip_prefix('!') {
+                Some(excluded) => exclude.push(excluded.to_string()),
+                None => include.push(pattern.clone()),
+            }
+        }
+        (include, exclude)
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct ExtensionState {
-    pub copied_files: Vec<CopiedFile>,
-    pub folders: Vec<Folder>,
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FileSelection {
+    pub start_line: u32,
+    pub end_line: u32,
+    pub content: String,
+    // What `fit_to_budget` left of `content`. It is rendered instead, while
+    // `content` stays as captured so the range can still be found on refresh.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trimmed: Option<String>,
 }
 
-impl Default for ExtensionState {
-    fn default() -> Self {
+impl FileSelection {
+    pub fn new(start_line: u32, end_line: u32, content: String) -> Self {
         Self {
-            copied_files: Vec::new(),
-            folders: Vec::new(),
+            start_line,
+            end_line,
+            content,
+            trimmed: None,
         }
     }
-}
 
-impl ExtensionState {
-    pub fn new() -> Self {
-        Self::default()
+    pub fn rendered(&self) -> &str {
+        self.trimmed.as_deref().unwrap_or(&self.content)
     }
 
-    pub fn add_copied_file(&mut self, copied_file: CopiedFile) {
-        // Remove existing file with same base path
-        self.copied_files.retain(|f| f.base_path != copied_file.base_path);
-        self.copied_files.push(copied_file);
+    pub fn format_path_with_lines(&self, base_path: &str) -> String {
+        format!("{}:{}", base_path, self.format_lines())
     }
 
-    pub fn clear_copied_files(&mut self) {
-        self.copied_files.clear();
+    pub fn format_lines(&self) -> String {
+        if self.start_line == self.end_line {
+            self.start_line.to_string()
+        } else {
+            format!("{}-{}", self.start_line, self.end_line)
+        }
     }
 
-    pub fn add_folder(&mut self, folder: Folder) {
-        self.folders.push(folder);
+    // Where the stored content now sits among `lines`; see `anchor::relocate`
+    pub fn relocate(&self, lines: &[&str]) -> Option<Relocation> {
+        anchor::relocate(self, lines)
     }
 
-    pub fn remove_folder(&mut self, folder_id: &str) -> bool {
-        if let Some(index) = self.folders.iter().position(|f| f.id == folder_id) {
-            self.folders.remove(index);
-            true
-        } else {
-            false
+    // Overlapping or directly adjacent ranges
+    pub fn touches(&self, other: &FileSelection) -> bool {
+        self.start_line <= other.end_line.saturating_add(1)
+            && other.start_line <= self.end_line.saturating_add(1)
+    }
+
+    // Combines two touching selections; lines present in both are taken from
+    // `newer`. The result is captured text again, so any trimming is dropped.
+    pub fn merge(self, newer: FileSelection) -> FileSelection {
+        let start_line = self.start_line.min(newer.start_line);
+        let end_line = self.end_line.max(newer.end_line);
+
+        let mut lines = BTreeMap::new();
+        for selection in [&self, &newer] {
+            for (offset, line) in selection.content.split('\n').enumerate() {
+                let line_number = selection.start_line + offset as u32;
+                if line_number > selection.end_line {
+                    break;
+                }
+                lines.insert(line_number, line);
+            }
         }
+
+        let content = lines.into_values().collect::<Vec<_>>().join("\n");
+        FileSelection::new(start_line, end_line, content)
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    pub fn find_folder(&self, folder_id: &str) -> Option<&Folder> {
-        self.folders.iter().find(|f| f.id == folder_id)
+    fn selection(start_line: u32, end_line: u32) -> FileSelection {
+        let content = (start_line..=end_line)
+            .map(|n| format!("line {}", n))
+            .collect::<Vec<_>>()
+            .join("\n");
+        FileSelection::new(start_line, end_line, content)
     }
 
-    pub fn find_folder_mut(&mut self, folder_id: &str) -> Option<&mut Folder> {
-        self.folders.iter_mut().find(|f| f.id == folder_id)
+    #[test]
+    fn test_touches() {
+        assert!(selection(1, 5).touches(&selection(3, 8)));
+        assert!(selection(1, 5).touches(&selection(6, 8)));
+        assert!(selection(6, 8).touches(&selection(1, 5)));
+        assert!(!selection(1, 5).touches(&selection(7, 8)));
     }
 
-    pub fn generate_combined_content(&self) -> String {
-        self.copied_files
-            .iter()
-            .map(|f| format!("{}\n\n{}", f.display_path, f.content))
-            .collect::<Vec<_>>()
-            .join("\n\n---\n\n")
+    #[test]
+    fn test_merge_prefers_newer_lines() {
+        let newer = FileSelection::new(4, 6, "NEW 4\nNEW 5\nNEW 6".to_string());
+        let merged = selection(1, 5).merge(newer);
+
+        assert_eq!(merged.start_line, 1);
+        assert_eq!(merged.end_line, 6);
+        assert_eq!(merged.content, "line 1\nline 2\nline 3\nNEW 4\nNEW 5\nNEW 6");
     }
 
-    pub fn copied_files_count(&self) -> usize {
-        self.copied_files.len()
+    #[test]
+    fn test_add_selection_keeps_disjoint_ranges_sorted() {
+        let mut file = CopiedFile::from_selection("a.rs".to_string(), selection(80, 81));
+        file.add_selection(selection(10, 11));
+
+        assert_eq!(file.selections.len(), 2);
+        assert_eq!(file.display_path, "a.rs:10-11,80-81");
+        assert_eq!(file.content, "line 10\nline 11\n…\nline 80\nline 81");
     }
 
-    pub fn folders_count(&self) -> usize {
-        self.folders.len()
+    #[test]
+    fn test_add_selection_merges_bridged_ranges() {
+        let mut file = CopiedFile::from_selection("a.rs".to_string(), selection(1, 3));
+        file.add_selection(selection(10, 12));
+        file.add_selection(selection(4, 9));
+
+        assert_eq!(file.selections, vec![selection(1, 12)]);
+        assert_eq!(file.display_path, "a.rs:1-12");
     }
-}
 
-#[derive(Debug, Clone)]
-pub struct FileSelection {
-    pub start_line: u32,
-    pub end_line: u32,
-    pub content: String,
-}
+    #[test]
+    fn test_content_hash_is_stable() {
+        assert_eq!(content_hash(""), "cbf29ce484222325");
+        assert_eq!(content_hash("a"), "af63dc4c8601ec8c");
+        assert_ne!(content_hash("fn a() {}"), content_hash("fn a() { }"));
+    }
 
-impl FileSelection {
-    pub fn new(start_line: u32, end_line: u32, content: String) -> Self {
-        Self {
-            start_line,
-            end_line,
-            content,
-        }
+    #[test]
+    fn test_recapture_reslices_edited_selections() {
+        let mut file = CopiedFile::from_selection("a.rs".to_string(), selection(1, 4));
+        file.add_selection(selection(6, 6));
+        let old_hash = file.captured_hash();
+
+        let source = "line 1\nline 2\nEDITED\nline 4\nline 5\nline 6\n";
+        let recaptured = file.recapture(source).unwrap();
+
+        assert_eq!(recaptured.display_path, "a.rs:1-4,6");
+        assert_eq!(recaptured.content, "line 1\nline 2\nEDITED\nline 4\n…\nline 6");
+        assert_ne!(recaptured.captured_hash(), old_hash);
     }
 
-    pub fn format_path_with_lines(&self, base_path: &str) -> String {
-        if self.start_line == self.end_line {
-            format!("{}:{}", base_path, self.start_line)
-        } else {
-            format!("{}:{}-{}", base_path, self.start_line, self.end_line)
-        }
+    #[test]
+    fn test_recapture_whole_file_and_past_the_end() {
+        let file = CopiedFile::new("a.rs".to_string(), "a.rs".to_string(), "old".to_string());
+        assert_eq!(file.recapture("new").unwrap().content, "new");
+
+        let partial = CopiedFile::from_selection("a.rs".to_string(), selection(4, 6));
+        let error = partial.recapture("line 1\nline 2").unwrap_err();
+        assert_eq!(error, "lines 4-6 could not be found (best match 0% similar at 1-2)");
+        assert_eq!(partial.recapture("").unwrap_err(), "the file is empty");
+    }
+
+    #[test]
+    fn test_recapture_follows_moved_selections() {
+        let mut file = CopiedFile::from_selection("a.rs".to_string(), selection(2, 3));
+        file.add_selection(selection(5, 5));
+
+        let source = "new 1\nnew 2\nline 1\nline 2\nline 3\nline 4\nline 5";
+        let recaptured = file.recapture(source).unwrap();
+
+        assert_eq!(recaptured.display_path, "a.rs:4-5,7");
+        assert_eq!(recaptured.content, "line 2\nline 3\n…\nline 5");
+        assert_eq!(recaptured.captured_hash(), file.captured_hash());
     }
 }
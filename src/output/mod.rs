@@ -0,0 +1,279 @@
+use crate::language::detect_language;
+use crate::models::CopiedFile;
+use serde::{Deserialize, Serialize};
+
+pub trait OutputFormatter {
+    fn render(&self, files: &[CopiedFile]) -> String;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    #[default]
+    Plain,
+    Markdown,
+    Xml,
+    Json,
+}
+
+impl OutputFormat {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "plain" | "text" => Ok(OutputFormat::Plain),
+            "markdown" | "md" => Ok(OutputFormat::Markdown),
+            "xml" => Ok(OutputFormat::Xml),
+            "json" => Ok(OutputFormat::Json),
+            other => Err(format!(
+                "Unknown output format '{}' (expected 'plain', 'markdown', 'xml' or 'json')",
+                other
+            )),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            OutputFormat::Plain => "plain",
+            OutputFormat::Markdown => "markdown",
+            OutputFormat::Xml => "xml",
+            OutputFormat::Json => "json",
+        }
+    }
+
+    pub fn formatter(&self) -> Box<dyn OutputFormatter> {
+        match self {
+            OutputFormat::Plain => Box::new(PlainFormatter),
+            OutputFormat::Markdown => Box::new(MarkdownFormatter),
+            OutputFormat::Xml => Box::new(XmlFormatter),
+            OutputFormat::Json => Box::new(JsonFormatter),
+        }
+    }
+}
+
+pub struct PlainFormatter;
+
+impl OutputFormatter for PlainFormatter {
+    fn render(&self, files: &[CopiedFile]) -> String {
+        files
+            .iter()
+            .map(|f| format!("{}\n\n{}", f.display_path, f.content))
+            .collect::<Vec<_>>()
+            .join("\n\n---\n\n")
+    }
+}
+
+pub struct MarkdownFormatter;
+
+impl OutputFormatter for MarkdownFormatter {
+    fn render(&self, files: &[CopiedFile]) -> String {
+        files
+            .iter()
+            .map(|file| {
+                let language = detect_language(&file.base_path, &file.content).unwrap_or("");
+                let fence = fence_for(&file.content);
+
+                format!(
+                    "### {}\n\n{}{}\n{}{}{}",
+                    code_span(&file.display_path),
+                    fence,
+                    language,
+                    file.content,
+                    trailing_newline(&file.content),
+                    fence
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    }
+}
+
+pub struct XmlFormatter;
+
+impl OutputFormatter for XmlFormatter {
+    fn render(&self, files: &[CopiedFile]) -> String {
+        let mut output = String::from("<files>\n");
+
+        for file in files {
+            output.push_str(&format!("<file path=\"{}\"", escape_xml(&file.display_path)));
+            if let Some(language) = detect_language(&file.base_path, &file.content) {
+                output.push_str(&format!(" language=\"{}\"", language));
+            }
+            output.push_str(">\n");
+            output.push_str(&escape_xml(&file.content));
+            output.push_str(trailing_newline(&file.content));
+            output.push_str("</file>\n");
+        }
+
+        output.push_str("</files>");
+        output
+    }
+}
+
+pub struct JsonFormatter;
+
+#[derive(Serialize)]
+struct JsonFile<'a> {
+    path: &'a str,
+    base_path: &'a str,
+    language: Option<&'static str>,
+    content: &'a str,
+}
+
+impl OutputFormatter for JsonFormatter {
+    fn render(&self, files: &[CopiedFile]) -> String {
+        let entries: Vec<JsonFile> = files
+            .iter()
+            .map(|file| JsonFile {
+                path: &file.display_path,
+                base_path: &file.base_path,
+                language: detect_language(&file.base_path, &file.content),
+                content: &file.content,
+            })
+            .collect();
+
+        // Serializing borrowed strings into a Vec cannot fail
+        serde_json::to_string_pretty(&entries).unwrap_or_default()
+    }
+}
+
+// A fence must be longer than any backtick run inside the content, otherwise
+// a ``` line in the copied file would close the block early.
+fn fence_for(content: &str) -> String {
+    "`".repeat((longest_backtick_run(content) + 1).max(3))
+}
+
+// Inline code for a path. As with fences, the delimiter outgrows the backtick
+// runs inside; a space keeps a leading or trailing backtick off the delimiter
+// and is stripped again by Markdown renderers.
+fn code_span(text: &str) -> String {
+    let delimiter = "`".repeat(longest_backtick_run(text) + 1);
+    let padding = if text.starts_with('`') || text.ends_with('`') { " " } else { "" };
+    format!("{}{}{}{}{}", delimiter, padding, text, padding, delimiter)
+}
+
+fn longest_backtick_run(text: &str) -> usize {
+    let mut longest_run = 0;
+    let mut current_run = 0;
+
+    for c in text.chars() {
+        if c == '`' {
+            current_run += 1;
+            longest_run = longest_run.max(current_run);
+        } else {
+            current_run = 0;
+        }
+    }
+
+    longest_run
+}
+
+fn trailing_newline(content: &str) -> &'static str {
+    if content.ends_with('\n') {
+        ""
+    } else {
+        "\n"
+    }
+}
+
+fn escape_xml(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(display_path: &str, base_path: &str, content: &str) -> CopiedFile {
+        CopiedFile::new(display_path.to_string(), base_path.to_string(), content.to_string())
+    }
+
+    #[test]
+    fn test_parse_output_format() {
+        assert_eq!(OutputFormat::parse("markdown").unwrap(), OutputFormat::Markdown);
+        assert_eq!(OutputFormat::parse(" MD ").unwrap(), OutputFormat::Markdown);
+        assert_eq!(OutputFormat::parse("plain").unwrap(), OutputFormat::Plain);
+        assert_eq!(OutputFormat::parse("xml").unwrap(), OutputFormat::Xml);
+        assert_eq!(OutputFormat::parse("JSON").unwrap(), OutputFormat::Json);
+        assert!(OutputFormat::parse("html").is_err());
+    }
+
+    #[test]
+    fn test_render_plain() {
+        let files = vec![file("a.rs", "a.rs", "fn a() {}"), file("b.rs", "b.rs", "fn b() {}")];
+        assert_eq!(
+            PlainFormatter.render(&files),
+            "a.rs\n\nfn a() {}\n\n---\n\nb.rs\n\nfn b() {}"
+        );
+    }
+
+    #[test]
+    fn test_render_markdown_with_selection() {
+        let files = vec![file("src/lib.rs:10-20", "src/lib.rs", "fn main() {}")];
+        assert_eq!(
+            MarkdownFormatter.render(&files),
+            "### `src/lib.rs:10-20`\n\n```rust\nfn main() {}\n```"
+        );
+    }
+
+    #[test]
+    fn test_render_markdown_unknown_language() {
+        let files = vec![file("notes.xyz", "notes.xyz", "hello\n")];
+        assert_eq!(MarkdownFormatter.render(&files), "### `notes.xyz`\n\n```\nhello\n```");
+    }
+
+    #[test]
+    fn test_fence_longer_than_backtick_runs() {
+        assert_eq!(fence_for("no ticks"), "```");
+        assert_eq!(fence_for("inline `code` only"), "```");
+        assert_eq!(fence_for("```rust\n```"), "````");
+        assert_eq!(fence_for("`````"), "``````");
+    }
+
+    #[test]
+    fn test_markdown_heading_survives_backticks_in_paths() {
+        assert_eq!(code_span("src/lib.rs"), "`src/lib.rs`");
+        assert_eq!(code_span("docs/a`b.md"), "``docs/a`b.md``");
+        assert_eq!(code_span("``x``"), "``` ``x`` ```");
+
+        let files = vec![file("a`b.rs:1", "a`b.rs", "fn a() {}")];
+        assert_eq!(MarkdownFormatter.render(&files), "### ``a`b.rs:1``\n\n```rust\nfn a() {}\n```");
+    }
+
+    #[test]
+    fn test_render_xml_escapes_content() {
+        let files = vec![file("src/a&b.rs:1-2", "src/a&b.rs", "if a < b { \"x\" }")];
+        assert_eq!(
+            XmlFormatter.render(&files),
+            "<files>\n<file path=\"src/a&amp;b.rs:1-2\" language=\"rust\">\n\
+             if a &lt; b { &quot;x&quot; }\n</file>\n</files>"
+        );
+    }
+
+    #[test]
+    fn test_render_json() {
+        let files = vec![file("app.py:3", "app.py", "print(1)")];
+        let value: serde_json::Value =
+            serde_json::from_str(&JsonFormatter.render(&files)).unwrap();
+
+        assert_eq!(value[0]["path"], "app.py:3");
+        assert_eq!(value[0]["base_path"], "app.py");
+        assert_eq!(value[0]["language"], "python");
+        assert_eq!(value[0]["content"], "print(1)");
+    }
+
+    #[test]
+    fn test_format_selects_formatter() {
+        let files = vec![file("a.rs", "a.rs", "x")];
+        assert_eq!(OutputFormat::Plain.formatter().render(&files), PlainFormatter.render(&files));
+        assert_eq!(OutputFormat::Json.formatter().render(&files), JsonFormatter.render(&files));
+    }
+}
@@ -0,0 +1,242 @@
+use crate::models::CopiedFile;
+use crate::output::OutputFormatter;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+// How stored (workspace-relative) paths are shown in rendered output
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DisplayMode {
+    #[default]
+    Relative,
+    Absolute,
+    // Relative, prefixed with the name of the workspace root directory
+    Project,
+}
+
+impl DisplayMode {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "relative" => Ok(DisplayMode::Relative),
+            "absolute" => Ok(DisplayMode::Absolute),
+            "project" => Ok(DisplayMode::Project),
+            other => Err(format!(
+                "Unknown path display mode '{}' (expected relative, absolute or project)",
+                other
+            )),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            DisplayMode::Relative => "relative",
+            DisplayMode::Absolute => "absolute",
+            DisplayMode::Project => "project",
+        }
+    }
+}
+
+// Turns paths as given by the editor or the user into the single form that is
+// stored: relative to the workspace root with `/` separators, or absolute when
+// outside of it
+#[derive(Debug, Clone)]
+pub struct Workspace {
+    root: PathBuf,
+    resolve_symlinks: bool,
+    display: DisplayMode,
+}
+
+impl Workspace {
+    // A relative `root` is taken from the current directory
+    pub fn new(root: &str, resolve_symlinks: bool, display: DisplayMode) -> Self {
+        let root = Path::new(root);
+        let root = if root.is_absolute() {
+            root.to_path_buf()
+        } else {
+            std::env::current_dir().unwrap_or_default().join(root)
+        };
+
+        let mut root = lexical_normalize(&root);
+        if resolve_symlinks {
+            root = fs::canonicalize(&root).unwrap_or(root);
+        }
+
+        Self {
+            root,
+            resolve_symlinks,
+            display,
+        }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn normalize(&self, path: &str) -> String {
+        let path = path.replace('\\', "/");
+        let mut absolute = lexical_normalize(&self.root.join(&path));
+        if self.resolve_symlinks {
+            // Files that do not exist (yet) keep their lexical form
+            absolute = fs::canonicalize(&absolute).unwrap_or(absolute);
+        }
+
+        match absolute.strip_prefix(&self.root) {
+            Ok(relative) if relative.as_os_str().is_empty() => ".".to_string(),
+            Ok(relative) => to_slash(relative),
+            Err(_) => to_slash(&absolute),
+        }
+    }
+
+    // Where a stored path lives on disk
+    pub fn absolute(&self, stored: &str) -> PathBuf {
+        self.root.join(stored)
+    }
+
+    pub fn display(&self, stored: &str) -> String {
+        if Path::new(stored).is_absolute() {
+            return stored.to_string();
+        }
+
+        match self.display {
+            DisplayMode::Relative => stored.to_string(),
+            DisplayMode::Absolute => to_slash(&self.absolute(stored)),
+            DisplayMode::Project => match self.root.file_name() {
+                Some(project) => format!("{}/{}", project.to_string_lossy(), stored),
+                None => stored.to_string(),
+            },
+        }
+    }
+}
+
+// Resolves `.` and `..` without touching the file system; `..` never climbs
+// above the root of an absolute path
+fn lexical_normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !normalized.pop() && !normalized.has_root() {
+                    normalized.push("..");
+                }
+            }
+            other => normalized.push(other.as_os_str()),
+        }
+    }
+    normalized
+}
+
+fn to_slash(path: &Path) -> String {
+    path.to_string_lossy().replace('\\', "/")
+}
+
+// Renders with another formatter after applying the workspace display mode to
+// every path
+pub struct DisplayPathFormatter {
+    inner: Box<dyn OutputFormatter>,
+    workspace: Workspace,
+}
+
+impl DisplayPathFormatter {
+    pub fn new(inner: Box<dyn OutputFormatter>, workspace: Workspace) -> Self {
+        Self { inner, workspace }
+    }
+}
+
+impl OutputFormatter for DisplayPathFormatter {
+    fn render(&self, files: &[CopiedFile]) -> String {
+        let displayed: Vec<CopiedFile> = files
+            .iter()
+            .map(|file| {
+                let mut file = file.clone();
+                // The line ranges of a partial copy follow the base path
+                file.rebase(self.workspace.display(&file.base_path));
+                file
+            })
+            .collect();
+        self.inner.render(&displayed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::PlainFormatter;
+
+    fn workspace(display: DisplayMode) -> Workspace {
+        Workspace::new("/home/me/proj", false, display)
+    }
+
+    #[test]
+    fn test_equivalent_paths_normalize_to_one_form() {
+        let workspace = workspace(DisplayMode::Relative);
+
+        for path in ["src/lib.rs", "./src/lib.rs", "src/./lib.rs", "src/api/../lib.rs", "/home/me/proj/src/lib.rs"] {
+            assert_eq!(workspace.normalize(path), "src/lib.rs", "{}", path);
+        }
+        assert_eq!(workspace.normalize("src\\api\\users.rs"), "src/api/users.rs");
+        assert_eq!(workspace.normalize("."), ".");
+    }
+
+    #[test]
+    fn test_paths_outside_the_workspace_stay_absolute() {
+        let workspace = workspace(DisplayMode::Relative);
+
+        assert_eq!(workspace.normalize("../other/main.rs"), "/home/me/other/main.rs");
+        assert_eq!(workspace.normalize("/etc/hosts"), "/etc/hosts");
+        assert_eq!(workspace.normalize("/home/me/project/a.rs"), "/home/me/project/a.rs");
+    }
+
+    #[test]
+    fn test_display_modes() {
+        assert_eq!(workspace(DisplayMode::Relative).display("src/lib.rs"), "src/lib.rs");
+        assert_eq!(workspace(DisplayMode::Absolute).display("src/lib.rs"), "/home/me/proj/src/lib.rs");
+        assert_eq!(workspace(DisplayMode::Project).display("src/lib.rs"), "proj/src/lib.rs");
+        assert_eq!(workspace(DisplayMode::Project).display("/etc/hosts"), "/etc/hosts");
+        assert!(DisplayMode::parse("full").is_err());
+    }
+
+    #[test]
+    fn test_display_formatter_keeps_line_ranges() {
+        let file = CopiedFile::new("src/lib.rs:3-4".to_string(), "src/lib.rs".to_string(), "fn a() {}".to_string());
+
+        let formatter = DisplayPathFormatter::new(Box::new(PlainFormatter), workspace(DisplayMode::Project));
+        assert!(formatter.render(&[file]).starts_with("proj/src/lib.rs:3-4"));
+    }
+
+    #[test]
+    fn test_display_formatter_handles_mismatched_display_paths() {
+        // As a hand-edited state file could leave them; the cut would fall inside "é"
+        let files = [
+            CopiedFile::new("aé".to_string(), "ab".to_string(), String::new()),
+            CopiedFile::new("x".to_string(), "src/lib.rs".to_string(), String::new()),
+        ];
+
+        let formatter = DisplayPathFormatter::new(Box::new(PlainFormatter), workspace(DisplayMode::Project));
+        let rendered = formatter.render(&files);
+        assert!(rendered.starts_with("proj/ab\n"), "{}", rendered);
+        assert!(rendered.contains("proj/src/lib.rs\n"), "{}", rendered);
+    }
+
+    #[test]
+    fn test_symlinks_resolve_when_enabled() {
+        let dir = std::env::temp_dir().join(format!("copy-path-paths-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("real")).unwrap();
+        fs::write(dir.join("real/a.rs"), "").unwrap();
+
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(dir.join("real"), dir.join("link")).unwrap();
+            let root = dir.to_string_lossy().to_string();
+
+            let lexical = Workspace::new(&root, false, DisplayMode::Relative);
+            assert_eq!(lexical.normalize("link/a.rs"), "link/a.rs");
+            let resolved = Workspace::new(&root, true, DisplayMode::Relative);
+            assert_eq!(resolved.normalize("link/a.rs"), "real/a.rs");
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,151 @@
+use crate::error::Error;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+
+// How command results are written back to the caller
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ResponseFormat {
+    // The message, followed by one `Warning:` line per warning
+    #[default]
+    Text,
+    // An envelope with `ok`, `data`, `error` and `warnings`
+    Json,
+}
+
+impl ResponseFormat {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "text" => Ok(ResponseFormat::Text),
+            "json" => Ok(ResponseFormat::Json),
+            other => Err(format!("Unknown response format '{}' (expected 'text' or 'json')", other)),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ResponseFormat::Text => "text",
+            ResponseFormat::Json => "json",
+        }
+    }
+
+    pub fn render(&self, result: &Result<Response, Error>) -> String {
+        match self {
+            ResponseFormat::Text => render_text(result),
+            ResponseFormat::Json => envelope(result).to_string(),
+        }
+    }
+}
+
+// What a successful command produced: the message shown to people and the
+// same result as data. `data` is always an object whose keys depend only on
+// the command.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Response {
+    pub message: String,
+    pub data: Value,
+    pub warnings: Vec<String>,
+}
+
+impl Response {
+    pub fn new(message: impl Into<String>, data: Value) -> Self {
+        debug_assert!(data.is_object(), "response data must be an object");
+        Self {
+            message: message.into(),
+            data,
+            warnings: Vec::new(),
+        }
+    }
+
+    pub fn with_warning(mut self, warning: impl Into<String>) -> Self {
+        self.warnings.push(warning.into());
+        self
+    }
+}
+
+fn render_text(result: &Result<Response, Error>) -> String {
+    match result {
+        Ok(response) => {
+            let mut lines = vec![response.message.clone()];
+            lines.extend(response.warnings.iter().map(|warning| format!("Warning: {}", warning)));
+            lines.join("\n")
+        }
+        Err(error) => format!("Error: {}", error),
+    }
+}
+
+pub fn envelope(result: &Result<Response, Error>) -> Value {
+    match result {
+        Ok(response) => json!({
+            "ok": true,
+            "data": response.data,
+            "error": null,
+            "warnings": response.warnings,
+        }),
+        Err(error) => json!({
+            "ok": false,
+            "data": null,
+            "error": error_data(error),
+            "warnings": [],
+        }),
+    }
+}
+
+pub fn error_data(error: &Error) -> Value {
+    let context: Map<String, Value> = error
+        .context()
+        .into_iter()
+        .map(|(name, value)| (name.to_string(), Value::String(value)))
+        .collect();
+    json!({
+        "code": error.code(),
+        "message": error.to_string(),
+        "context": context,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_text_appends_warnings() {
+        let response = Response::new("Copied 1 files to clipboard", json!({})).with_warning("over budget");
+        assert_eq!(
+            ResponseFormat::Text.render(&Ok(response)),
+            "Copied 1 files to clipboard\nWarning: over budget"
+        );
+
+        let error = Error::FolderNotFound { folder: "docs".to_string() };
+        assert_eq!(ResponseFormat::Text.render(&Err(error)), "Error: Folder 'docs' not found");
+    }
+
+    #[test]
+    fn test_json_envelope() {
+        let response = Response::new("Folder 'Docs' created successfully", json!({ "folder_id": "folder_1" }));
+        let rendered: Value = serde_json::from_str(&ResponseFormat::Json.render(&Ok(response))).unwrap();
+        assert_eq!(
+            rendered,
+            json!({ "ok": true, "data": { "folder_id": "folder_1" }, "error": null, "warnings": [] })
+        );
+
+        let error = Error::FileAlreadyInFolder {
+            folder: "folder_1".to_string(),
+            path: "src/lib.rs".to_string(),
+        };
+        let rendered: Value = serde_json::from_str(&ResponseFormat::Json.render(&Err(error))).unwrap();
+        assert_eq!(
+            rendered,
+            json!({
+                "ok": false,
+                "data": null,
+                "error": {
+                    "code": "file_already_in_folder",
+                    "message": "File 'src/lib.rs' already exists in folder",
+                    "context": { "folder_id": "folder_1", "path": "src/lib.rs" },
+                },
+                "warnings": [],
+            })
+        );
+    }
+}
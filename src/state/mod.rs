@@ -0,0 +1,184 @@
+use crate::clipboard::ClipboardManager;
+use crate::folders::FolderManager;
+use crate::output::{OutputFormat, OutputFormatter};
+use crate::paths::{DisplayMode, DisplayPathFormatter, Workspace};
+use crate::response::ResponseFormat;
+use crate::templates::TemplateRegistry;
+use crate::tokens::{TokenBudget, TokenEstimator};
+use serde::{Deserialize, Serialize};
+
+// The single authoritative state of the extension. The managers are flattened
+// so their fields sit directly in the persisted document.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ExtensionState {
+    #[serde(flatten)]
+    pub clipboard: ClipboardManager,
+    #[serde(flatten)]
+    pub folder_manager: FolderManager,
+    #[serde(default)]
+    pub output_format: OutputFormat,
+    #[serde(default)]
+    pub templates: TemplateRegistry,
+    #[serde(default)]
+    pub token_estimator: TokenEstimator,
+    #[serde(default)]
+    pub token_budget: Option<TokenBudget>,
+    // Folder patterns are resolved against this; the current directory when unset
+    #[serde(default)]
+    pub workspace_root: Option<String>,
+    // Whether symlinks are resolved when paths are normalized on entry
+    #[serde(default)]
+    pub resolve_symlinks: bool,
+    #[serde(default)]
+    pub path_display: DisplayMode,
+    #[serde(default)]
+    pub response_format: ResponseFormat,
+}
+
+impl ExtensionState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn workspace_root(&self) -> &str {
+        self.workspace_root.as_deref().unwrap_or(".")
+    }
+
+    pub fn workspace(&self) -> Workspace {
+        Workspace::new(self.workspace_root(), self.resolve_symlinks, self.path_display)
+    }
+
+    // Re-expresses every stored path against the current workspace after the
+    // root changed from that of `previous`
+    pub fn rebase_paths(&mut self, previous: &Workspace) {
+        let workspace = self.workspace();
+        let rebase = |stored: &str| workspace.normalize(&previous.absolute(stored).to_string_lossy());
+        self.clipboard.map_paths(rebase);
+        self.folder_manager.map_files(rebase);
+    }
+
+    // Applies the path display mode on top of `formatter`
+    pub fn display_formatter(&self, formatter: Box<dyn OutputFormatter>) -> Box<dyn OutputFormatter> {
+        match self.path_display {
+            DisplayMode::Relative => formatter,
+            _ => Box::new(DisplayPathFormatter::new(formatter, self.workspace())),
+        }
+    }
+
+    // Tokens of the active buffer as rendered with the current output format
+    pub fn clipboard_tokens(&self) -> usize {
+        let formatter = self.display_formatter(self.output_format.formatter());
+        let rendered = self.clipboard.render(formatter.as_ref());
+        self.token_estimator.count(&rendered)
+    }
+
+    pub fn status_message(&self) -> String {
+        let mut clipboard = self.clipboard.get_status_message();
+        if self.clipboard.has_files() {
+            clipboard.push_str(&format!(" (~{} tokens", self.clipboard_tokens()));
+            if let Some(budget) = &self.token_budget {
+                clipboard.push_str(&format!(" of {} budget", budget.limit));
+            }
+            clipboard.push(')');
+        }
+
+        format!(
+            "Clipboard: {} | Folders: {} | Total folder files: {}",
+            clipboard,
+            self.folder_manager.get_folder_count(),
+            self.folder_manager.get_total_files_count()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tokens::BudgetMode;
+
+    #[test]
+    fn test_serialized_layout() {
+        let mut state = ExtensionState::new();
+        state
+            .clipboard
+            .copy_file_with_content("a.rs".to_string(), "fn a() {}".to_string(), None)
+            .unwrap();
+        state
+            .folder_manager
+            .create_folder("Docs".to_string(), vec!["README.md".to_string()])
+            .unwrap();
+
+        let value = serde_json::to_value(&state).unwrap();
+        assert_eq!(value["buffers"]["default"]["copied_files"][0]["base_path"], "a.rs");
+        assert_eq!(value["active_buffer"], "default");
+        assert_eq!(value["folders"][0]["name"], "Docs");
+        assert_eq!(value["output_format"], "plain");
+        assert!(value["templates"].as_array().unwrap().is_empty());
+
+        let restored: ExtensionState = serde_json::from_value(value).unwrap();
+        assert_eq!(restored.clipboard.get_file_count(), 1);
+        assert_eq!(restored.folder_manager.get_folder_count(), 1);
+    }
+
+    #[test]
+    fn test_rebase_paths_follows_the_workspace_root() {
+        let mut state = ExtensionState::new();
+        state.workspace_root = Some("/home/dev/project".to_string());
+        state
+            .clipboard
+            .copy_file_with_content("src/lib.rs".to_string(), "fn a() {}".to_string(), None)
+            .unwrap();
+        state
+            .clipboard
+            .copy_file_with_content("/etc/hosts".to_string(), "localhost".to_string(), None)
+            .unwrap();
+        state
+            .folder_manager
+            .create_folder("Docs".to_string(), vec!["README.md".to_string(), "../shared/notes.md".to_string()])
+            .unwrap();
+
+        let previous = state.workspace();
+        state.workspace_root = Some("/home/dev".to_string());
+        state.rebase_paths(&previous);
+
+        let paths: Vec<&str> = state.clipboard.get_files().iter().map(|f| f.base_path.as_str()).collect();
+        assert_eq!(paths, vec!["project/src/lib.rs", "/etc/hosts"]);
+        assert_eq!(state.clipboard.get_files()[0].display_path, "project/src/lib.rs");
+        assert_eq!(state.folder_manager.folders[0].files, vec!["project/README.md", "shared/notes.md"]);
+
+        // The history moves along with the entries
+        state.clipboard.active_mut().undo().unwrap();
+        assert_eq!(state.clipboard.get_files()[0].base_path, "project/src/lib.rs");
+    }
+
+    #[test]
+    fn test_status_message() {
+        let mut state = ExtensionState::new();
+        state
+            .folder_manager
+            .create_folder("Docs".to_string(), vec!["a.md".to_string(), "b.md".to_string()])
+            .unwrap();
+
+        assert_eq!(
+            state.status_message(),
+            "Clipboard: No files copied | Folders: 1 | Total folder files: 2"
+        );
+    }
+
+    #[test]
+    fn test_status_message_reports_tokens() {
+        let mut state = ExtensionState::new();
+        state.token_estimator = TokenEstimator::Chars;
+        state.token_budget = Some(TokenBudget::new(100, BudgetMode::Warn));
+        state
+            .clipboard
+            .copy_file_with_content("a.rs".to_string(), "x".repeat(28), None)
+            .unwrap();
+
+        // "a.rs\n\n" + 28 characters renders to 34 characters
+        assert_eq!(
+            state.status_message(),
+            "Clipboard: 1 file copied (~9 tokens of 100 budget) | Folders: 0 | Total folder files: 0"
+        );
+    }
+}
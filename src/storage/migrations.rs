@@ -0,0 +1,443 @@
+use crate::paths::{DisplayMode, Workspace};
+use serde_json::{json, Value};
+
+type Migration = fn(Value) -> Result<Value, String>;
+
+// MIGRATIONS[n] upgrades a version n document to version n + 1. Append a step
+// (and a frozen fixture in `fixtures/`) whenever the persisted format changes.
+const MIGRATIONS: &[Migration] = &[
+    migrate_v0_to_v1,
+    migrate_v1_to_v2,
+    migrate_v2_to_v3,
+    migrate_v3_to_v4,
+    migrate_v4_to_v5,
+    migrate_v5_to_v6,
+];
+
+pub const CURRENT_VERSION: u32 = MIGRATIONS.len() as u32;
+
+pub fn document_version(document: &Value) -> Result<u32, String> {
+    match document.get("version") {
+        None => Ok(0),
+        Some(version) => version
+            .as_u64()
+            .map(|v| v as u32)
+            .ok_or_else(|| format!("invalid version field: {}", version)),
+    }
+}
+
+pub fn migrate(mut document: Value) -> Result<Value, String> {
+    let version = document_version(&document)?;
+
+    if version > CURRENT_VERSION {
+        return Err(format!(
+            "state version {} is newer than supported version {}",
+            version, CURRENT_VERSION
+        ));
+    }
+
+    for (from, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        document = migration(document)
+            .map_err(|e| format!("migration from version {} failed: {}", from, e))?;
+    }
+
+    Ok(document)
+}
+
+// Version 0 is the bare `ExtensionState` written before the document envelope existed
+fn migrate_v0_to_v1(document: Value) -> Result<Value, String> {
+    if !document.is_object() {
+        return Err("expected a JSON object".to_string());
+    }
+
+    Ok(json!({
+        "version": 1,
+        "state": document,
+    }))
+}
+
+// Version 2 stores the line ranges of partial copies explicitly instead of only
+// encoding them in `display_path` as `path:start-end`
+fn migrate_v1_to_v2(mut document: Value) -> Result<Value, String> {
+    let copied_files = document
+        .pointer_mut("/state/copied_files")
+        .and_then(Value::as_array_mut)
+        .ok_or("expected state.copied_files to be an array")?;
+
+    for copied_file in copied_files {
+        let selections = match legacy_line_range(copied_file) {
+            Some((start_line, end_line)) => json!([{
+                "start_line": start_line,
+                "end_line": end_line,
+                "content": copied_file["content"].clone(),
+            }]),
+            None => json!([]),
+        };
+        copied_file["selections"] = selections;
+    }
+
+    document["version"] = json!(2);
+    Ok(document)
+}
+
+// Version 3 records when each entry was copied. The real time is unknown for
+// older entries, so they all get the epoch and keep their order under a stable sort.
+fn migrate_v2_to_v3(mut document: Value) -> Result<Value, String> {
+    let copied_files = document
+        .pointer_mut("/state/copied_files")
+        .and_then(Value::as_array_mut)
+        .ok_or("expected state.copied_files to be an array")?;
+
+    for copied_file in copied_files {
+        copied_file["copied_at"] = json!("1970-01-01T00:00:00Z");
+    }
+
+    document["version"] = json!(3);
+    Ok(document)
+}
+
+// Version 4 supports named clipboard buffers; the single copy buffer becomes
+// the default buffer
+fn migrate_v3_to_v4(mut document: Value) -> Result<Value, String> {
+    let state = document
+        .get_mut("state")
+        .and_then(Value::as_object_mut)
+        .ok_or("expected state to be an object")?;
+
+    let copied_files = state
+        .remove("copied_files")
+        .ok_or("expected state.copied_files")?;
+    state.insert(
+        "buffers".to_string(),
+        json!({ "default": { "copied_files": copied_files } }),
+    );
+    state.insert("active_buffer".to_string(), json!("default"));
+
+    document["version"] = json!(4);
+    Ok(document)
+}
+
+// Version 5 guarantees unique folder ids. Timestamp ids could collide when two
+// folders were created in the same millisecond; later duplicates get a suffix.
+fn migrate_v4_to_v5(mut document: Value) -> Result<Value, String> {
+    let folders = document
+        .pointer_mut("/state/folders")
+        .and_then(Value::as_array_mut)
+        .ok_or("expected state.folders to be an array")?;
+
+    let mut seen = std::collections::HashSet::new();
+    for folder in folders {
+        let id = folder["id"]
+            .as_str()
+            .ok_or("expected folder ids to be strings")?
+            .to_string();
+
+        let mut unique = id.clone();
+        let mut suffix = 2;
+        while !seen.insert(unique.clone()) {
+            unique = format!("{}_{}", id, suffix);
+            suffix += 1;
+        }
+        folder["id"] = json!(unique);
+    }
+
+    document["version"] = json!(5);
+    Ok(document)
+}
+
+// Version 6 stores every path in the normalized workspace-relative form. Older
+// states kept paths as they were given, so `./a.rs` and `a.rs` could both be
+// stored; entries that normalize to the same path are merged.
+fn migrate_v5_to_v6(mut document: Value) -> Result<Value, String> {
+    let state = document.get_mut("state").ok_or("expected state to be an object")?;
+    let workspace = Workspace::new(
+        state["workspace_root"].as_str().unwrap_or("."),
+        state["resolve_symlinks"].as_bool().unwrap_or(false),
+        DisplayMode::Relative,
+    );
+
+    if let Some(folders) = state.get_mut("folders").and_then(Value::as_array_mut) {
+        for folder in folders {
+            let files = folder["files"].as_array().ok_or("expected folder files to be an array")?;
+            let mut normalized: Vec<Value> = Vec::new();
+            for file in files {
+                let file = json!(workspace.normalize(file.as_str().ok_or("expected folder files to be strings")?));
+                if !normalized.contains(&file) {
+                    normalized.push(file);
+                }
+            }
+            folder["files"] = json!(normalized);
+        }
+    }
+
+    if let Some(buffers) = state.get_mut("buffers").and_then(Value::as_object_mut) {
+        for buffer in buffers.values_mut() {
+            let copied_files = buffer
+                .get_mut("copied_files")
+                .and_then(Value::as_array_mut)
+                .ok_or("expected buffer copied_files to be an array")?;
+
+            let mut normalized: Vec<Value> = Vec::new();
+            for mut copied_file in copied_files.drain(..) {
+                let base_path = copied_file["base_path"]
+                    .as_str()
+                    .ok_or("expected base_path to be a string")?
+                    .to_string();
+                let display_path = copied_file["display_path"].as_str().unwrap_or(&base_path).to_string();
+                let ranges = display_path.strip_prefix(base_path.as_str()).unwrap_or("");
+
+                let base_path = workspace.normalize(&base_path);
+                copied_file["display_path"] = json!(format!("{}{}", base_path, ranges));
+                copied_file["base_path"] = json!(base_path);
+
+                // Like a recopy: the later entry replaces the earlier one in place
+                match normalized.iter().position(|f| f["base_path"] == copied_file["base_path"]) {
+                    Some(index) => normalized[index] = copied_file,
+                    None => normalized.push(copied_file),
+                }
+            }
+            *copied_files = normalized;
+        }
+    }
+
+    document["version"] = json!(6);
+    Ok(document)
+}
+
+fn legacy_line_range(copied_file: &Value) -> Option<(u32, u32)> {
+    let display_path = copied_file.get("display_path")?.as_str()?;
+    let base_path = copied_file.get("base_path")?.as_str()?;
+    let range = display_path.strip_prefix(base_path)?.strip_prefix(':')?;
+
+    match range.split_once('-') {
+        Some((start, end)) => Some((start.parse().ok()?, end.parse().ok()?)),
+        None => {
+            let line = range.parse().ok()?;
+            Some((line, line))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::ExtensionState;
+
+    const FIXTURES: &[&str] = &[
+        include_str!("fixtures/v0.json"),
+        include_str!("fixtures/v1.json"),
+        include_str!("fixtures/v2.json"),
+        include_str!("fixtures/v3.json"),
+        include_str!("fixtures/v4.json"),
+        include_str!("fixtures/v5.json"),
+        include_str!("fixtures/v6.json"),
+    ];
+
+    fn load_fixture(version: usize) -> ExtensionState {
+        let document: Value = serde_json::from_str(FIXTURES[version]).unwrap();
+        assert_eq!(document_version(&document).unwrap(), version as u32);
+
+        let migrated = migrate(document).unwrap();
+        assert_eq!(migrated["version"], CURRENT_VERSION);
+        serde_json::from_value(migrated["state"].clone()).unwrap()
+    }
+
+    #[test]
+    fn test_every_version_has_a_fixture() {
+        assert_eq!(FIXTURES.len(), CURRENT_VERSION as usize + 1);
+    }
+
+    #[test]
+    fn test_migrate_v0_fixture() {
+        let state = load_fixture(0);
+
+        assert_eq!(state.clipboard.get_files().len(), 1);
+        assert_eq!(state.clipboard.get_files()[0].display_path, "src/lib.rs:10-12");
+        assert_eq!(state.folder_manager.folders.len(), 1);
+        assert_eq!(state.folder_manager.folders[0].name, "API handlers");
+        assert_eq!(state.folder_manager.folders[0].files.len(), 2);
+        assert_eq!(state.folder_manager.folders[0].color.as_deref(), Some("blue"));
+    }
+
+    #[test]
+    fn test_migrate_v1_fixture() {
+        let state = load_fixture(1);
+
+        assert_eq!(state.folder_manager.folders[0].id, "folder_1703030400000");
+        assert_eq!(state.folder_manager.folders[0].files, vec!["src/api/users.rs", "src/api/orders.rs"]);
+        assert_eq!(state.templates.list(), vec!["chat"]);
+
+        let copied_file = &state.clipboard.get_files()[0];
+        assert_eq!(copied_file.selections.len(), 1);
+        assert_eq!(copied_file.selections[0].start_line, 10);
+        assert_eq!(copied_file.selections[0].end_line, 12);
+        assert_eq!(copied_file.selections[0].content, copied_file.content);
+    }
+
+    #[test]
+    fn test_migrate_v2_fixture() {
+        let state = load_fixture(2);
+
+        let copied_files = &state.clipboard.get_files();
+        assert_eq!(copied_files[0].display_path, "src/lib.rs:10-12,40");
+        assert_eq!(copied_files[0].selections.len(), 2);
+        assert!(!copied_files[1].is_partial());
+        assert_eq!(state.folder_manager.folders[0].files.len(), 2);
+    }
+
+    #[test]
+    fn test_migrate_v3_fixture() {
+        let state = load_fixture(3);
+
+        let copied_files = &state.clipboard.get_files();
+        assert_eq!(copied_files[0].copied_at.to_rfc3339(), "2024-12-20T10:00:00+00:00");
+        assert!(state.clipboard.preserve_position_on_recopy);
+    }
+
+    #[test]
+    fn test_migrate_v4_fixture() {
+        let state = load_fixture(4);
+
+        assert_eq!(state.clipboard.active_buffer, "bug-report");
+        assert_eq!(state.clipboard.get_files()[0].base_path, "src/api/users.rs");
+        assert_eq!(state.clipboard.buffer("default").unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_migrate_v5_fixture() {
+        let state = load_fixture(5);
+
+        let folders = &state.folder_manager.folders;
+        assert_eq!(folders[1].parent_id.as_deref(), Some("folder_1"));
+        assert_eq!(folders[1].patterns, vec!["src/api/**/*.rs", "!**/tests/**"]);
+        assert_eq!(state.folder_manager.next_folder_id, 2);
+        assert_eq!(state.workspace_root.as_deref(), Some("/home/dev/project"));
+        assert!(state.clipboard.get_files()[0].content_hash.is_some());
+    }
+
+    #[test]
+    fn test_migrate_v6_fixture() {
+        let state = load_fixture(6);
+
+        assert_eq!(state.clipboard.get_files()[0].display_path, "src/lib.rs:10-12");
+        assert_eq!(state.folder_manager.folders[0].files, vec!["src/main.rs", "/etc/hosts"]);
+        assert!(state.resolve_symlinks);
+        assert_eq!(state.response_format, crate::response::ResponseFormat::Json);
+    }
+
+    #[test]
+    fn test_stored_paths_are_normalized() {
+        let document = json!({
+            "version": 5,
+            "state": {
+                "buffers": {
+                    "default": {
+                        "copied_files": [
+                            { "display_path": "./src/lib.rs:3-4", "base_path": "./src/lib.rs", "content": "old" },
+                            { "display_path": "/home/dev/project/src/main.rs", "base_path": "/home/dev/project/src/main.rs", "content": "main" },
+                            { "display_path": "src/lib.rs", "base_path": "src/lib.rs", "content": "new" }
+                        ]
+                    }
+                },
+                "folders": [
+                    { "id": "folder_1", "name": "A", "files": ["src/api/../lib.rs", "./src/lib.rs", "../shared/a.rs"], "color": null }
+                ],
+                "workspace_root": "/home/dev/project"
+            }
+        });
+
+        let migrated = migrate_v5_to_v6(document).unwrap();
+        let state = &migrated["state"];
+        assert_eq!(
+            state["buffers"]["default"]["copied_files"],
+            json!([
+                { "display_path": "src/lib.rs", "base_path": "src/lib.rs", "content": "new" },
+                { "display_path": "src/main.rs", "base_path": "src/main.rs", "content": "main" }
+            ])
+        );
+        assert_eq!(state["folders"][0]["files"], json!(["src/lib.rs", "/home/dev/shared/a.rs"]));
+        assert_eq!(migrated["version"], 6);
+    }
+
+    #[test]
+    fn test_duplicate_folder_ids_are_made_unique() {
+        let document = json!({
+            "version": 4,
+            "state": {
+                "folders": [
+                    { "id": "folder_1703030400000", "name": "A", "files": [], "color": null },
+                    { "id": "folder_1703030400000", "name": "B", "files": [], "color": null },
+                    { "id": "folder_1703030400000_2", "name": "C", "files": [], "color": null },
+                    { "id": "folder_1703030400000", "name": "D", "files": [], "color": null }
+                ]
+            }
+        });
+
+        let migrated = migrate_v4_to_v5(document).unwrap();
+        let ids: Vec<&str> = migrated["state"]["folders"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|folder| folder["id"].as_str().unwrap())
+            .collect();
+        assert_eq!(
+            ids,
+            vec![
+                "folder_1703030400000",
+                "folder_1703030400000_2",
+                "folder_1703030400000_2_2",
+                "folder_1703030400000_3"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_single_buffer_becomes_default() {
+        let state = load_fixture(3);
+
+        assert_eq!(state.clipboard.active_buffer, "default");
+        assert_eq!(state.clipboard.buffers.len(), 1);
+        assert_eq!(state.clipboard.get_file_count(), 2);
+    }
+
+    #[test]
+    fn test_legacy_entries_get_epoch_copy_time() {
+        let state = load_fixture(2);
+        assert!(state
+            .clipboard
+            .get_files()
+            .iter()
+            .all(|f| f.copied_at.timestamp() == 0));
+    }
+
+    #[test]
+    fn test_whole_file_copies_get_no_selections() {
+        let document = json!({
+            "version": 1,
+            "state": {
+                "copied_files": [
+                    { "display_path": "a.rs", "base_path": "a.rs", "content": "x" },
+                    { "display_path": "b.rs:7", "base_path": "b.rs", "content": "y" }
+                ],
+                "folders": []
+            }
+        });
+
+        let migrated = migrate_v1_to_v2(document).unwrap();
+        assert_eq!(migrated["state"]["copied_files"][0]["selections"], json!([]));
+        assert_eq!(migrated["state"]["copied_files"][1]["selections"][0]["start_line"], 7);
+        assert_eq!(migrated["state"]["copied_files"][1]["selections"][0]["end_line"], 7);
+    }
+
+    #[test]
+    fn test_newer_version_is_rejected() {
+        let document = json!({ "version": CURRENT_VERSION + 1, "state": {} });
+        assert!(migrate(document).unwrap_err().contains("newer than supported"));
+    }
+
+    #[test]
+    fn test_invalid_version_is_rejected() {
+        assert!(migrate(json!({ "version": "one" })).is_err());
+        assert!(migrate(json!([1, 2, 3])).is_err());
+    }
+}
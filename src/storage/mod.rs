@@ -0,0 +1,293 @@
+mod migrations;
+
+use crate::error::Error;
+use crate::state::ExtensionState;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+
+pub use migrations::CURRENT_VERSION;
+
+pub const STATE_FILE_NAME: &str = "copy-path-with-code-state.json";
+
+#[derive(Deserialize)]
+struct PersistedDocument {
+    state: ExtensionState,
+}
+
+#[derive(Serialize)]
+struct PersistedDocumentRef<'a> {
+    version: u32,
+    state: &'a ExtensionState,
+}
+
+pub struct LoadedState {
+    pub state: ExtensionState,
+    pub warning: Option<String>,
+}
+
+pub struct Storage {
+    path: PathBuf,
+    // Set when the file on disk is from a newer version; it is then never written
+    newer_version: Option<u32>,
+}
+
+impl Storage {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            newer_version: None,
+        }
+    }
+
+    // Zed runs extensions with their work directory as the current directory
+    pub fn in_work_dir() -> Self {
+        Self::new(PathBuf::from(STATE_FILE_NAME))
+    }
+
+    #[cfg(test)]
+    pub fn path(&self) -> &std::path::Path {
+        &self.path
+    }
+
+    pub fn load(&mut self) -> LoadedState {
+        let raw = match fs::read_to_string(&self.path) {
+            Ok(raw) => raw,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                return LoadedState {
+                    state: ExtensionState::new(),
+                    warning: None,
+                };
+            }
+            Err(e) => return self.recover(format!("could not read state file: {}", e)),
+        };
+
+        // Not corrupt, just not ours to read: leave it for the version that wrote it
+        if let Some(version) = newer_version(&raw) {
+            self.newer_version = Some(version);
+            return LoadedState {
+                state: ExtensionState::new(),
+                warning: Some(self.too_new(version).to_string()),
+            };
+        }
+
+        match parse_document(&raw) {
+            Ok((state, version)) if version < CURRENT_VERSION => {
+                // Keep the original around until the migrated state has proven itself
+                let backup_path = self.sibling_path(&format!("v{}", version));
+                let warning = match fs::write(&backup_path, &raw) {
+                    Ok(()) => format!(
+                        "Migrated state from version {} to {}; the original was kept at {}",
+                        version,
+                        CURRENT_VERSION,
+                        backup_path.display()
+                    ),
+                    Err(e) => format!(
+                        "Migrated state from version {} to {} but could not back up the original: {}",
+                        version, CURRENT_VERSION, e
+                    ),
+                };
+
+                LoadedState {
+                    state,
+                    warning: Some(warning),
+                }
+            }
+            Ok((state, _)) => LoadedState {
+                state,
+                warning: None,
+            },
+            Err(reason) => self.recover(reason),
+        }
+    }
+
+    pub fn save(&self, state: &ExtensionState) -> Result<(), Error> {
+        if let Some(version) = self.newer_version {
+            return Err(self.too_new(version));
+        }
+
+        let document = PersistedDocumentRef {
+            version: CURRENT_VERSION,
+            state,
+        };
+        let json = serde_json::to_string_pretty(&document)
+            .map_err(|e| Error::io(self.path.display(), &e.into()))?;
+
+        // Write to a sibling temp file first so a crash mid-write never
+        // leaves a truncated state file behind
+        let temp_path = self.sibling_path("tmp");
+        let mut file = fs::File::create(&temp_path).map_err(|e| Error::io(temp_path.display(), &e))?;
+        file.write_all(json.as_bytes())
+            .and_then(|_| file.sync_all())
+            .map_err(|e| Error::io(temp_path.display(), &e))?;
+
+        fs::rename(&temp_path, &self.path).map_err(|e| Error::io(self.path.display(), &e))
+    }
+
+    fn too_new(&self, version: u32) -> Error {
+        Error::StateFileTooNew {
+            path: self.path.display().to_string(),
+            version,
+            supported: CURRENT_VERSION,
+        }
+    }
+
+    fn recover(&self, reason: String) -> LoadedState {
+        let backup_path = self.sibling_path(&format!(
+            "corrupt-{}",
+            chrono::Utc::now().format("%Y%m%d%H%M%S")
+        ));
+
+        let warning = match fs::rename(&self.path, &backup_path) {
+            Ok(()) => format!(
+                "State file was unusable ({}); moved it to {} and started fresh",
+                reason,
+                backup_path.display()
+            ),
+            Err(e) => format!(
+                "State file was unusable ({}) and could not be backed up ({}); started fresh",
+                reason, e
+            ),
+        };
+
+        LoadedState {
+            state: ExtensionState::new(),
+            warning: Some(warning),
+        }
+    }
+
+    fn sibling_path(&self, suffix: &str) -> PathBuf {
+        let mut file_name = self
+            .path
+            .file_name()
+            .map(|name| name.to_os_string())
+            .unwrap_or_else(|| STATE_FILE_NAME.into());
+        file_name.push(format!(".{}", suffix));
+        self.path.with_file_name(file_name)
+    }
+}
+
+fn newer_version(raw: &str) -> Option<u32> {
+    let document: serde_json::Value = serde_json::from_str(raw).ok()?;
+    migrations::document_version(&document)
+        .ok()
+        .filter(|version| *version > CURRENT_VERSION)
+}
+
+fn parse_document(raw: &str) -> Result<(ExtensionState, u32), String> {
+    let document: serde_json::Value =
+        serde_json::from_str(raw).map_err(|e| format!("invalid JSON: {}", e))?;
+    let version = migrations::document_version(&document)?;
+
+    let document: PersistedDocument = serde_json::from_value(migrations::migrate(document)?)
+        .map_err(|e| format!("invalid state: {}", e))?;
+
+    Ok((document.state, version))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "copy-path-with-code-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_load_missing_file_returns_fresh_state() {
+        let dir = temp_dir("missing");
+        let loaded = Storage::new(dir.join(STATE_FILE_NAME)).load();
+
+        assert!(loaded.warning.is_none());
+        assert_eq!(loaded.state.clipboard.get_file_count(), 0);
+        assert_eq!(loaded.state.folder_manager.get_folder_count(), 0);
+    }
+
+    #[test]
+    fn test_save_and_load_round_trip() {
+        let dir = temp_dir("round-trip");
+        let mut storage = Storage::new(dir.join(STATE_FILE_NAME));
+
+        let mut state = ExtensionState::new();
+        state
+            .clipboard
+            .copy_file_with_content("a.rs".to_string(), "fn a() {}".to_string(), None)
+            .unwrap();
+        state
+            .folder_manager
+            .create_folder("API".to_string(), vec!["src/api.rs".to_string()])
+            .unwrap();
+
+        storage.save(&state).unwrap();
+        assert!(!storage.sibling_path("tmp").exists());
+
+        let loaded = storage.load();
+        assert!(loaded.warning.is_none());
+        assert_eq!(loaded.state.clipboard.get_files()[0].content, "fn a() {}");
+        assert_eq!(loaded.state.folder_manager.folders[0].files, vec!["src/api.rs".to_string()]);
+
+        let raw: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(storage.path()).unwrap()).unwrap();
+        assert_eq!(raw["version"], CURRENT_VERSION);
+    }
+
+    #[test]
+    fn test_corrupt_file_is_backed_up() {
+        let dir = temp_dir("corrupt");
+        let mut storage = Storage::new(dir.join(STATE_FILE_NAME));
+        fs::write(storage.path(), "{ not json").unwrap();
+
+        let loaded = storage.load();
+        assert!(loaded.warning.unwrap().contains("started fresh"));
+        assert_eq!(loaded.state.folder_manager.get_folder_count(), 0);
+        assert!(!storage.path().exists());
+
+        let backups: Vec<_> = fs::read_dir(&dir)
+            .unwrap()
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_name().to_string_lossy().contains(".corrupt-"))
+            .collect();
+        assert_eq!(backups.len(), 1);
+        assert_eq!(fs::read_to_string(backups[0].path()).unwrap(), "{ not json");
+    }
+
+    #[test]
+    fn test_older_version_is_migrated_and_kept() {
+        let dir = temp_dir("older");
+        let mut storage = Storage::new(dir.join(STATE_FILE_NAME));
+        let original = r#"{"copied_files": [], "folders": [{"id": "f1", "name": "Docs", "files": ["README.md"], "color": null}]}"#;
+        fs::write(storage.path(), original).unwrap();
+
+        let loaded = storage.load();
+        assert!(loaded.warning.unwrap().contains("Migrated state from version 0"));
+        assert_eq!(loaded.state.folder_manager.folders[0].name, "Docs");
+        assert_eq!(fs::read_to_string(storage.sibling_path("v0")).unwrap(), original);
+    }
+
+    #[test]
+    fn test_newer_version_is_left_untouched() {
+        let dir = temp_dir("newer");
+        let mut storage = Storage::new(dir.join(STATE_FILE_NAME));
+        let original = r#"{"version": 999, "state": {"folders": [{"id": "folder_1", "name": "Docs"}]}}"#;
+        fs::write(storage.path(), original).unwrap();
+
+        let loaded = storage.load();
+        assert!(loaded.warning.unwrap().contains("newer than supported"));
+        assert_eq!(loaded.state.folder_manager.get_folder_count(), 0);
+
+        let error = storage.save(&loaded.state).unwrap_err();
+        assert_eq!(error.code(), "state_file_too_new");
+        assert!(error.context().contains(&("version", "999".to_string())));
+
+        assert_eq!(fs::read_to_string(storage.path()).unwrap(), original);
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+    }
+}
@@ -0,0 +1,475 @@
+use crate::error::Error;
+use crate::language::detect_language;
+use crate::models::CopiedFile;
+use crate::output::OutputFormatter;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OutputTemplate {
+    pub name: String,
+    #[serde(default)]
+    pub header: String,
+    pub file: String,
+    #[serde(default)]
+    pub separator: String,
+    #[serde(default)]
+    pub footer: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Placeholder {
+    Path,
+    BasePath,
+    Lines,
+    Language,
+    Content,
+    Index,
+    Count,
+    Timestamp,
+}
+
+impl Placeholder {
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "path" => Some(Placeholder::Path),
+            "base_path" => Some(Placeholder::BasePath),
+            "lines" => Some(Placeholder::Lines),
+            "language" => Some(Placeholder::Language),
+            "content" => Some(Placeholder::Content),
+            "index" => Some(Placeholder::Index),
+            "count" => Some(Placeholder::Count),
+            "timestamp" => Some(Placeholder::Timestamp),
+            _ => None,
+        }
+    }
+
+    fn is_per_file(&self) -> bool {
+        !matches!(self, Placeholder::Count | Placeholder::Timestamp)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    Literal(String),
+    Placeholder(Placeholder),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Section {
+    Header,
+    File,
+    Separator,
+    Footer,
+}
+
+impl Section {
+    fn name(&self) -> &'static str {
+        match self {
+            Section::Header => "header",
+            Section::File => "file",
+            Section::Separator => "separator",
+            Section::Footer => "footer",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CompiledTemplate {
+    pub template: OutputTemplate,
+    header: Vec<Segment>,
+    file: Vec<Segment>,
+    separator: Vec<Segment>,
+    footer: Vec<Segment>,
+}
+
+impl CompiledTemplate {
+    pub fn compile(template: OutputTemplate) -> Result<Self, String> {
+        if template.name.trim().is_empty() {
+            return Err("Template name cannot be empty".to_string());
+        }
+
+        let header = parse_section(&template, Section::Header, &template.header)?;
+        let file = parse_section(&template, Section::File, &template.file)?;
+        let separator = parse_section(&template, Section::Separator, &template.separator)?;
+        let footer = parse_section(&template, Section::Footer, &template.footer)?;
+
+        Ok(Self {
+            template,
+            header,
+            file,
+            separator,
+            footer,
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.template.name
+    }
+
+    pub fn render_at(&self, files: &[CopiedFile], timestamp: DateTime<Utc>) -> String {
+        let timestamp = timestamp.to_rfc3339();
+        let count = files.len();
+        let mut output = String::new();
+
+        render_segments(&mut output, &self.header, None, count, &timestamp);
+        for (index, file) in files.iter().enumerate() {
+            if index > 0 {
+                render_segments(&mut output, &self.separator, None, count, &timestamp);
+            }
+            render_segments(&mut output, &self.file, Some((index, file)), count, &timestamp);
+        }
+        render_segments(&mut output, &self.footer, None, count, &timestamp);
+
+        output
+    }
+}
+
+impl OutputFormatter for CompiledTemplate {
+    fn render(&self, files: &[CopiedFile]) -> String {
+        self.render_at(files, Utc::now())
+    }
+}
+
+fn parse_section(
+    template: &OutputTemplate,
+    section: Section,
+    source: &str,
+) -> Result<Vec<Segment>, String> {
+    let mut segments = Vec::new();
+    let mut literal = String::new();
+    let mut chars = source.char_indices().peekable();
+
+    while let Some((position, c)) = chars.next() {
+        match c {
+            '{' if chars.peek().map(|(_, next)| *next) == Some('{') => {
+                chars.next();
+                literal.push('{');
+            }
+            '}' if chars.peek().map(|(_, next)| *next) == Some('}') => {
+                chars.next();
+                literal.push('}');
+            }
+            '{' => {
+                let mut name = String::new();
+                let mut closed = false;
+                for (_, c) in chars.by_ref() {
+                    if c == '}' {
+                        closed = true;
+                        break;
+                    }
+                    name.push(c);
+                }
+
+                if !closed {
+                    return Err(format!(
+                        "Template '{}': unclosed '{{' at position {} in {} section",
+                        template.name,
+                        position,
+                        section.name()
+                    ));
+                }
+
+                let placeholder = Placeholder::parse(&name).ok_or_else(|| {
+                    format!(
+                        "Template '{}': unknown placeholder '{{{}}}' in {} section \
+                         (expected one of {{path}}, {{base_path}}, {{lines}}, {{language}}, \
+                         {{content}}, {{index}}, {{count}}, {{timestamp}})",
+                        template.name,
+                        name,
+                        section.name()
+                    )
+                })?;
+
+                if placeholder.is_per_file() && section != Section::File {
+                    return Err(format!(
+                        "Template '{}': placeholder '{{{}}}' is only available in the file section, \
+                         not the {} section",
+                        template.name,
+                        name,
+                        section.name()
+                    ));
+                }
+
+                if !literal.is_empty() {
+                    segments.push(Segment::Literal(std::mem::take(&mut literal)));
+                }
+                segments.push(Segment::Placeholder(placeholder));
+            }
+            '}' => {
+                return Err(format!(
+                    "Template '{}': unmatched '}}' at position {} in {} section (use '}}}}' for a literal brace)",
+                    template.name,
+                    position,
+                    section.name()
+                ));
+            }
+            _ => literal.push(c),
+        }
+    }
+
+    if !literal.is_empty() {
+        segments.push(Segment::Literal(literal));
+    }
+
+    Ok(segments)
+}
+
+fn render_segments(
+    output: &mut String,
+    segments: &[Segment],
+    file: Option<(usize, &CopiedFile)>,
+    count: usize,
+    timestamp: &str,
+) {
+    for segment in segments {
+        match segment {
+            Segment::Literal(text) => output.push_str(text),
+            Segment::Placeholder(Placeholder::Count) => output.push_str(&count.to_string()),
+            Segment::Placeholder(Placeholder::Timestamp) => output.push_str(timestamp),
+            Segment::Placeholder(placeholder) => {
+                // Per-file placeholders are rejected outside the file section at compile time
+                if let Some((index, file)) = file {
+                    output.push_str(&render_file_placeholder(*placeholder, index, file));
+                }
+            }
+        }
+    }
+}
+
+fn render_file_placeholder(placeholder: Placeholder, index: usize, file: &CopiedFile) -> String {
+    match placeholder {
+        Placeholder::Path => file.display_path.clone(),
+        Placeholder::BasePath => file.base_path.clone(),
+        Placeholder::Lines => line_range(file).to_string(),
+        Placeholder::Language => detect_language(&file.base_path, &file.content)
+            .unwrap_or("")
+            .to_string(),
+        Placeholder::Content => file.content.clone(),
+        Placeholder::Index => (index + 1).to_string(),
+        Placeholder::Count | Placeholder::Timestamp => String::new(),
+    }
+}
+
+fn line_range(file: &CopiedFile) -> &str {
+    file.display_path
+        .strip_prefix(file.base_path.as_str())
+        .and_then(|rest| rest.strip_prefix(':'))
+        .unwrap_or("")
+}
+
+// Persisted as the list of raw definitions; they are compiled again on load
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(from = "Vec<OutputTemplate>", into = "Vec<OutputTemplate>")]
+pub struct TemplateRegistry {
+    templates: Vec<CompiledTemplate>,
+    // Stored definitions that no longer validate, written back unchanged so a
+    // save does not lose them
+    invalid: Vec<OutputTemplate>,
+    load_errors: Vec<String>,
+}
+
+impl TemplateRegistry {
+    pub fn new() -> Self {
+        Self {
+            templates: Vec::new(),
+            invalid: Vec::new(),
+            load_errors: Vec::new(),
+        }
+    }
+
+    // Stored templates that no longer validate are skipped rather than
+    // failing the whole state load
+    pub fn take_load_errors(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.load_errors)
+    }
+
+    // Reports whether a template of the same name was replaced
+    pub fn define(&mut self, template: OutputTemplate) -> Result<bool, Error> {
+        let name = template.name.clone();
+        let compiled = CompiledTemplate::compile(template).map_err(|reason| Error::InvalidTemplate { name, reason })?;
+        self.invalid.retain(|t| t.name != compiled.name());
+
+        if let Some(existing) = self.templates.iter_mut().find(|t| t.name() == compiled.name()) {
+            *existing = compiled;
+            Ok(true)
+        } else {
+            self.templates.push(compiled);
+            Ok(false)
+        }
+    }
+
+    // Also removes a stored definition that did not validate
+    pub fn remove(&mut self, name: &str) -> Result<(), Error> {
+        let count = self.templates.len() + self.invalid.len();
+        self.templates.retain(|t| t.name() != name);
+        self.invalid.retain(|t| t.name != name);
+        if self.templates.len() + self.invalid.len() == count {
+            return Err(Error::TemplateNotFound { name: name.to_string() });
+        }
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Result<&CompiledTemplate, Error> {
+        self.templates
+            .iter()
+            .find(|t| t.name() == name)
+            .ok_or_else(|| Error::TemplateNotFound { name: name.to_string() })
+    }
+
+    pub fn list(&self) -> Vec<&str> {
+        self.templates.iter().map(|t| t.name()).collect()
+    }
+
+    pub fn definitions(&self) -> Vec<OutputTemplate> {
+        self.templates
+            .iter()
+            .map(|t| t.template.clone())
+            .chain(self.invalid.iter().cloned())
+            .collect()
+    }
+}
+
+impl From<Vec<OutputTemplate>> for TemplateRegistry {
+    fn from(definitions: Vec<OutputTemplate>) -> Self {
+        let mut registry = Self::new();
+        for template in definitions {
+            if let Err(error) = registry.define(template.clone()) {
+                registry.load_errors.push(error.to_string());
+                registry.invalid.push(template);
+            }
+        }
+        registry
+    }
+}
+
+impl From<TemplateRegistry> for Vec<OutputTemplate> {
+    fn from(registry: TemplateRegistry) -> Self {
+        registry.definitions()
+    }
+}
+
+impl Default for TemplateRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn template(header: &str, file: &str, separator: &str, footer: &str) -> OutputTemplate {
+        OutputTemplate {
+            name: "test".to_string(),
+            header: header.to_string(),
+            file: file.to_string(),
+            separator: separator.to_string(),
+            footer: footer.to_string(),
+        }
+    }
+
+    fn files() -> Vec<CopiedFile> {
+        vec![
+            CopiedFile::new("src/lib.rs:3-4".to_string(), "src/lib.rs".to_string(), "a\nb".to_string()),
+            CopiedFile::new("main.py".to_string(), "main.py".to_string(), "print(1)".to_string()),
+        ]
+    }
+
+    #[test]
+    fn test_render_all_sections() {
+        let compiled = CompiledTemplate::compile(template(
+            "{count} files @ {timestamp}\n",
+            "[{index}/{count}] {path} ({base_path} {lines}) {language}\n{content}",
+            "\n--\n",
+            "\nend",
+        ))
+        .unwrap();
+        let timestamp = Utc.with_ymd_and_hms(2024, 1, 2, 3, 4, 5).unwrap();
+
+        assert_eq!(
+            compiled.render_at(&files(), timestamp),
+            "2 files @ 2024-01-02T03:04:05+00:00\n\
+             [1/2] src/lib.rs:3-4 (src/lib.rs 3-4) rust\na\nb\n--\n\
+             [2/2] main.py (main.py ) python\nprint(1)\nend"
+        );
+    }
+
+    #[test]
+    fn test_escaped_braces() {
+        let compiled = CompiledTemplate::compile(template("", "{{{path}}}", "", "")).unwrap();
+        assert_eq!(compiled.render_at(&files()[1..], Utc::now()), "{main.py}");
+    }
+
+    #[test]
+    fn test_unknown_placeholder_is_rejected() {
+        let error = CompiledTemplate::compile(template("", "{path} {file_name}", "", "")).unwrap_err();
+        assert!(error.contains("unknown placeholder '{file_name}'"));
+        assert!(error.contains("file section"));
+    }
+
+    #[test]
+    fn test_per_file_placeholder_outside_file_section_is_rejected() {
+        let error = CompiledTemplate::compile(template("{path}", "{content}", "", "")).unwrap_err();
+        assert!(error.contains("only available in the file section"));
+    }
+
+    #[test]
+    fn test_unbalanced_braces_are_rejected() {
+        assert!(CompiledTemplate::compile(template("", "{path", "", "")).is_err());
+        assert!(CompiledTemplate::compile(template("", "path}", "", "")).is_err());
+    }
+
+    #[test]
+    fn test_registry_define_and_get() {
+        let mut registry = TemplateRegistry::new();
+        registry.define(template("", "<<{path}>>\n{content}", "", "")).unwrap();
+
+        assert_eq!(registry.list(), vec!["test"]);
+        assert!(registry.get("test").is_ok());
+        assert!(registry.get("missing").is_err());
+
+        registry.remove("test").unwrap();
+        assert!(registry.list().is_empty());
+    }
+
+    #[test]
+    fn test_registry_skips_invalid_stored_templates() {
+        let mut registry: TemplateRegistry = serde_json::from_str(
+            r#"[{"name": "ok", "file": "{path}"}, {"name": "bad", "file": "{nope}"}]"#,
+        )
+        .unwrap();
+
+        assert_eq!(registry.list(), vec!["ok"]);
+        let errors = registry.take_load_errors();
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].contains("'bad'"));
+    }
+
+    #[test]
+    fn test_invalid_stored_templates_are_written_back() {
+        let stored = r#"[{"name":"ok","header":"","file":"{path}","separator":"","footer":""},{"name":"bad","header":"","file":"{nope}","separator":"","footer":""}]"#;
+        let mut registry: TemplateRegistry = serde_json::from_str(stored).unwrap();
+        assert_eq!(serde_json::to_string(&registry).unwrap(), stored);
+
+        // Redefining or deleting the broken template replaces the stored definition
+        let mut fixed = template("", "{path}", "", "");
+        fixed.name = "bad".to_string();
+        assert!(!registry.define(fixed).unwrap());
+        assert_eq!(registry.definitions().len(), 2);
+
+        let mut registry: TemplateRegistry = serde_json::from_str(stored).unwrap();
+        registry.remove("bad").unwrap();
+        assert_eq!(registry.definitions().len(), 1);
+        assert!(registry.remove("bad").is_err());
+    }
+
+    #[test]
+    fn test_registry_rejects_invalid_definition() {
+        let mut registry = TemplateRegistry::new();
+        assert!(registry.define(template("", "{oops}", "", "")).is_err());
+        assert!(registry.define(template("{path", "{path}", "", "")).is_err());
+        assert!(registry.list().is_empty());
+    }
+}
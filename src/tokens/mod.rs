@@ -0,0 +1,267 @@
+use crate::models::CopiedFile;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TokenEstimator {
+    // Approximates how BPE tokenizers split text into words, numbers and symbols
+    #[default]
+    Bpe,
+    // One token per four characters
+    Chars,
+}
+
+impl TokenEstimator {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "bpe" => Ok(TokenEstimator::Bpe),
+            "chars" => Ok(TokenEstimator::Chars),
+            other => Err(format!(
+                "Unknown token estimator '{}' (expected 'bpe' or 'chars')",
+                other
+            )),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            TokenEstimator::Bpe => "bpe",
+            TokenEstimator::Chars => "chars",
+        }
+    }
+
+    pub fn count(&self, text: &str) -> usize {
+        match self {
+            TokenEstimator::Bpe => estimate_bpe(text),
+            TokenEstimator::Chars => text.chars().count().div_ceil(4),
+        }
+    }
+
+    pub fn count_file(&self, file: &CopiedFile) -> usize {
+        self.count(&file.display_path) + self.count(&file.content)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BudgetMode {
+    Warn,
+    Refuse,
+}
+
+impl BudgetMode {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "warn" => Ok(BudgetMode::Warn),
+            "refuse" => Ok(BudgetMode::Refuse),
+            other => Err(format!(
+                "Unknown budget mode '{}' (expected 'warn' or 'refuse')",
+                other
+            )),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            BudgetMode::Warn => "warn",
+            BudgetMode::Refuse => "refuse",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TokenBudget {
+    pub limit: usize,
+    pub mode: BudgetMode,
+}
+
+impl TokenBudget {
+    pub fn new(limit: usize, mode: BudgetMode) -> Self {
+        Self { limit, mode }
+    }
+
+    // Explains an exceeded budget, or returns None when `total` fits
+    pub fn check(
+        &self,
+        estimator: TokenEstimator,
+        total: usize,
+        files: &[CopiedFile],
+    ) -> Option<String> {
+        if total <= self.limit {
+            return None;
+        }
+
+        let contributors = largest_contributors(estimator, files, 3)
+            .iter()
+            .map(|(path, tokens)| format!("{} (~{} tokens)", path, tokens))
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        Some(format!(
+            "~{} tokens exceeds the budget of {} tokens; largest files: {}",
+            total, self.limit, contributors
+        ))
+    }
+}
+
+pub fn largest_contributors(
+    estimator: TokenEstimator,
+    files: &[CopiedFile],
+    limit: usize,
+) -> Vec<(String, usize)> {
+    let mut counts: Vec<(String, usize)> = files
+        .iter()
+        .map(|file| (file.display_path.clone(), estimator.count_file(file)))
+        .collect();
+
+    counts.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+    counts.truncate(limit);
+    counts
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum CharClass {
+    Letter,
+    Digit,
+    Space,
+    Newline,
+    Symbol,
+    Other,
+}
+
+fn classify(c: char) -> CharClass {
+    if c == '\n' {
+        CharClass::Newline
+    } else if c.is_whitespace() {
+        CharClass::Space
+    } else if c.is_ascii_alphabetic() || c == '_' {
+        CharClass::Letter
+    } else if c.is_ascii_digit() {
+        CharClass::Digit
+    } else if c.is_ascii() {
+        CharClass::Symbol
+    } else {
+        CharClass::Other
+    }
+}
+
+// Mirrors the pre-tokenization step of GPT-style BPE vocabularies: text is cut
+// into runs of letters, digits, whitespace and symbols, and each run is costed
+// by how such runs typically merge.
+fn estimate_bpe(text: &str) -> usize {
+    let chars: Vec<char> = text.chars().collect();
+    let mut tokens = 0;
+    let mut start = 0;
+
+    while start < chars.len() {
+        let class = classify(chars[start]);
+        let mut end = start + 1;
+        while end < chars.len() && classify(chars[end]) == class && class != CharClass::Other {
+            end += 1;
+        }
+
+        let run = &chars[start..end];
+        tokens += match class {
+            CharClass::Letter => word_tokens(run),
+            // Numbers are split into groups of up to three digits
+            CharClass::Digit => run.len().div_ceil(3),
+            // A single space merges into the following word
+            CharClass::Space if run.len() == 1 && end < chars.len() => 0,
+            // Indentation merges into long whitespace tokens
+            CharClass::Space => run.len().div_ceil(4),
+            CharClass::Newline => 1,
+            // Common operator pairs such as `::`, `->` or `()` are single tokens
+            CharClass::Symbol => run.len().div_ceil(2),
+            // Non-ASCII characters rarely merge and often take more than one token
+            CharClass::Other => run.iter().map(|c| c.len_utf8().div_ceil(2)).sum(),
+        };
+
+        start = end;
+    }
+
+    tokens
+}
+
+// Identifiers are split at `_` and lower-to-upper case changes, and each part
+// costs roughly one token per six letters, since common words are single tokens
+fn word_tokens(word: &[char]) -> usize {
+    let mut tokens = 0;
+    let mut part_len: usize = 0;
+
+    for (index, &c) in word.iter().enumerate() {
+        let boundary = c == '_'
+            || (index > 0 && c.is_ascii_uppercase() && word[index - 1].is_ascii_lowercase());
+
+        if boundary && part_len > 0 {
+            tokens += part_len.div_ceil(6);
+            part_len = 0;
+        }
+        if c == '_' {
+            tokens += 1;
+        } else {
+            part_len += 1;
+        }
+    }
+
+    if part_len > 0 {
+        tokens += part_len.div_ceil(6);
+    }
+
+    tokens.max(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chars_estimator() {
+        assert_eq!(TokenEstimator::Chars.count(""), 0);
+        assert_eq!(TokenEstimator::Chars.count("abcd"), 1);
+        assert_eq!(TokenEstimator::Chars.count("abcde"), 2);
+    }
+
+    #[test]
+    fn test_bpe_estimator_on_prose() {
+        assert_eq!(TokenEstimator::Bpe.count(""), 0);
+        assert_eq!(TokenEstimator::Bpe.count("the cat sat"), 3);
+        assert_eq!(TokenEstimator::Bpe.count("hello, world"), 3);
+    }
+
+    #[test]
+    fn test_bpe_estimator_on_code() {
+        // fn | main | () | space+{ | newline | indent | println | !( | "hi" ...
+        let tokens = TokenEstimator::Bpe.count("fn main() {\n    println!(\"hi\");\n}");
+        assert!((10..=20).contains(&tokens), "unexpected estimate {}", tokens);
+
+        assert_eq!(word_tokens(&"copy_file".chars().collect::<Vec<_>>()), 3);
+        assert_eq!(word_tokens(&"copyFile".chars().collect::<Vec<_>>()), 2);
+        assert_eq!(TokenEstimator::Bpe.count("1234567"), 3);
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(TokenEstimator::parse("CHARS").unwrap(), TokenEstimator::Chars);
+        assert!(TokenEstimator::parse("words").is_err());
+        assert_eq!(BudgetMode::parse("refuse").unwrap(), BudgetMode::Refuse);
+        assert!(BudgetMode::parse("block").is_err());
+    }
+
+    #[test]
+    fn test_budget_check_lists_largest_files() {
+        let files = vec![
+            CopiedFile::new("small.rs".to_string(), "small.rs".to_string(), "a".repeat(4)),
+            CopiedFile::new("big.rs".to_string(), "big.rs".to_string(), "a".repeat(400)),
+            CopiedFile::new("mid.rs".to_string(), "mid.rs".to_string(), "a".repeat(40)),
+        ];
+        let budget = TokenBudget::new(50, BudgetMode::Warn);
+
+        assert!(budget.check(TokenEstimator::Chars, 50, &files).is_none());
+
+        let message = budget.check(TokenEstimator::Chars, 120, &files).unwrap();
+        assert!(message.starts_with("~120 tokens exceeds the budget of 50 tokens"));
+        let big = message.find("big.rs").unwrap();
+        let mid = message.find("mid.rs").unwrap();
+        assert!(big < mid);
+    }
+}